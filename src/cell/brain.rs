@@ -1,21 +1,55 @@
 use rand::{Rng, Isaac64Rng};
 use itertools::Itertools;
+use std::cmp::{min, max};
 use mli;
 use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use super::Rules;
+use super::controller::Controller;
+use super::genes::Genes;
+use super::super::fluid::TOTAL_PUMPED;
 
 // 0.0, 0.5, -0.5, 1.0, -1.0, 2.0, -2.0, MAX, MIN
 pub const CONST_INPUTS: usize = 9;
-// Inhale, Fluids (7 * 8), neighbor present (6).
-pub const STATIC_INPUTS: usize = 1 + 7 * 8 + 6;
-pub const TOTAL_MEMORY: usize = 4;
+const CONST_VALUES: [f64; CONST_INPUTS] = [0.0,
+                                           0.5,
+                                           -0.5,
+                                           1.0,
+                                           -1.0,
+                                           2.0,
+                                           -2.0,
+                                           ::std::f64::MAX,
+                                           ::std::f64::MIN];
+// Neighbor inhale, kinship, facing and last choice.
+pub const NEIGHBOR_SENSES: usize = 4;
+// Distant fluids (6 * 8) and cell counts (6).
+pub const DISTANT_INPUTS: usize = 6 * 8 + 6;
+// Values sent to each touching neighbor, which it receives on the next cycle.
+pub const CONTACT_MESSAGE: usize = 2;
+// Inhale, Fluids (7 * 8), neighbor present (6), neighbor senses (6 * 4), distant senses,
+// received messages (6 * 2), age, walls (6), stored fluids (3).
+pub const STATIC_INPUTS: usize = 1 + 7 * 8 + 6 + 6 * NEIGHBOR_SENSES + DISTANT_INPUTS +
+                                 6 * CONTACT_MESSAGE + 1 + 6 + TOTAL_PUMPED;
+// The most memory registers a brain can evolve to use.
+pub const TOTAL_MEMORY: usize = 8;
+pub const DEFAULT_MEMORY: usize = 4;
 pub const TOTAL_INPUTS: usize = CONST_INPUTS + STATIC_INPUTS + TOTAL_MEMORY;
-// Coefficients (8 * 6), Movement(7), Mate(13), Divide, Turn(6), Explode, Suicide
-pub const STATIC_OUTPUTS: usize = 8 * 6 + 7 + 13 + 1 + 6 + 1 + 1;
+// Values a parent can write into the memory of its offspring.
+pub const BIRTH_MESSAGE: usize = 4;
+// Coefficients (8 * 6), Movement(7), Mate(13), Divide, Turn(6), Explode, Suicide, Birth message,
+// sent messages (6 * 2), Transfer(7), Receptive, Pump(3), Build(7), Dig(7)
+pub const STATIC_OUTPUTS: usize = 8 * 6 + 7 + 13 + 1 + 6 + 1 + 1 + BIRTH_MESSAGE +
+                                  6 * CONTACT_MESSAGE + 7 + 1 + 3 + 7 + 7;
 pub const TOTAL_OUTPUTS: usize = STATIC_OUTPUTS + TOTAL_MEMORY;
 pub const DEFAULT_MUTATE_SIZE: usize = 8;
 pub const DEFAULT_CROSSOVER_POINTS: usize = 1;
 pub const DEFAULT_INSTRUCTIONS: usize = 256;
-const MUTATE_PROBABILITY: f64 = 1.0;
+const MIN_INSTRUCTIONS: usize = TOTAL_OUTPUTS;
+const MAX_INSTRUCTIONS: usize = 1024;
+pub const DEFAULT_MUTATE_PROBABILITY: f64 = 1.0;
+// Chances for an offspring to gain or lose an instruction or memory register.
+const INSERT_PROBABILITY: f64 = 0.05;
+const DELETE_PROBABILITY: f64 = 0.05;
+const MEMORY_MUTATE_PROBABILITY: f64 = 0.05;
 
 type MepType = mli::Mep<Ins,
                         Isaac64Rng,
@@ -23,7 +57,7 @@ type MepType = mli::Mep<Ins,
                         fn(&mut Ins, &mut Isaac64Rng),
                         fn(&Ins, f64, f64) -> f64>;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Ins {
     _NOP,
     _ADD,
@@ -41,9 +75,243 @@ pub enum Ins {
     _LN,
     _SIN,
     _SQT,
+    _ABS,
+    _MIN,
+    _MAX,
+    _TNH,
+    _NEG,
+    _SEL,
+    _COS,
     MAX,
 }
 
+pub const TOTAL_INSTRUCTIONS: usize = Ins::MAX as usize;
+
+/// Instructions that were added after the original set.
+pub const EXTENDED_INSTRUCTIONS: [Ins; 7] = [Ins::_ABS,
+                                             Ins::_MIN,
+                                             Ins::_MAX,
+                                             Ins::_TNH,
+                                             Ins::_NEG,
+                                             Ins::_SEL,
+                                             Ins::_COS];
+
+impl Ins {
+    /// The number of operands the instruction reads.
+    pub fn arity(&self) -> usize {
+        match *self {
+            Ins::_NOP | Ins::_EXP | Ins::_LN | Ins::_SIN | Ins::_SQT | Ins::_ABS | Ins::_TNH |
+            Ins::_NEG | Ins::_COS => 1,
+            _ => 2,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Ins::_NOP => "NOP",
+            Ins::_ADD => "ADD",
+            Ins::_SUB => "SUB",
+            Ins::_MUL => "MUL",
+            Ins::_DIV => "DIV",
+            Ins::_MOD => "MOD",
+            Ins::_GRT => "GRT",
+            Ins::_LES => "LES",
+            Ins::_AND => "AND",
+            Ins::_OR => "OR",
+            Ins::_POW => "POW",
+            Ins::_EXP => "EXP",
+            Ins::_LOG => "LOG",
+            Ins::_LN => "LN",
+            Ins::_SIN => "SIN",
+            Ins::_SQT => "SQT",
+            Ins::_ABS => "ABS",
+            Ins::_MIN => "MIN",
+            Ins::_MAX => "MAX",
+            Ins::_TNH => "TNH",
+            Ins::_NEG => "NEG",
+            Ins::_SEL => "SEL",
+            Ins::_COS => "COS",
+            Ins::MAX => unreachable!(),
+        }
+    }
+}
+
+/// The instructions that may appear in new brains and mutations and the relative chance of each.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InstructionSet {
+    weights: Vec<f64>,
+}
+
+impl InstructionSet {
+    /// Every instruction with an equal weight.
+    pub fn new() -> Self {
+        InstructionSet { weights: vec![1.0; TOTAL_INSTRUCTIONS] }
+    }
+
+    pub fn weight(&self, ins: Ins) -> f64 {
+        self.weights[ins as usize]
+    }
+
+    /// A weight of zero disables the instruction.
+    pub fn set_weight(&mut self, ins: Ins, weight: f64) {
+        self.weights[ins as usize] = weight;
+    }
+
+    pub fn enable(&mut self, group: &[Ins]) {
+        for &ins in group {
+            self.set_weight(ins, 1.0);
+        }
+    }
+
+    pub fn disable(&mut self, group: &[Ins]) {
+        for &ins in group {
+            self.set_weight(ins, 0.0);
+        }
+    }
+
+    /// Picks an instruction with a probability proportional to its weight.
+    pub fn sample(&self, rng: &mut Isaac64Rng) -> Ins {
+        use std::mem;
+        let total = self.weights.iter().fold(0.0, |acc, &w| acc + w);
+        if total <= 0.0 {
+            return Ins::_NOP;
+        }
+        let mut choice = rng.gen_range(0.0, total);
+        for (i, &w) in self.weights.iter().enumerate() {
+            if choice < w {
+                return unsafe { mem::transmute(i as u8) };
+            }
+            choice -= w;
+        }
+        // Rounding can leave a sliver at the end, so use the last enabled instruction.
+        let last = self.weights.iter().rposition(|&w| w > 0.0).unwrap();
+        unsafe { mem::transmute(last as u8) }
+    }
+}
+
+/// One instruction of a program along with the indices of its operands.
+///
+/// Operands below `TOTAL_INPUTS` are inputs and the rest are the results of earlier instructions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Op {
+    pub ins: Ins,
+    pub first: usize,
+    pub second: usize,
+}
+
+/// The index of the instruction that produces an output.
+///
+/// The outputs are the last instructions of the program in reverse order.
+pub fn output_instruction(len: usize, output: usize) -> usize {
+    len - 1 - output
+}
+
+/// Marks which instructions contribute to at least one output.
+pub fn live(program: &[Op]) -> Vec<bool> {
+    let mut live = vec![false; program.len()];
+    for o in 0..TOTAL_OUTPUTS {
+        live[output_instruction(program.len(), o)] = true;
+    }
+    // Operands always come before the instruction that uses them.
+    for i in (0..program.len()).rev() {
+        if live[i] {
+            let op = &program[i];
+            if op.first >= TOTAL_INPUTS {
+                live[op.first - TOTAL_INPUTS] = true;
+            }
+            if op.ins.arity() == 2 && op.second >= TOTAL_INPUTS {
+                live[op.second - TOTAL_INPUTS] = true;
+            }
+        }
+    }
+    live
+}
+
+/// Where a compiled instruction finds one of its operands.
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    Input(usize),
+    Const(f64),
+    Slot(usize),
+}
+
+/// A program reduced to the instructions that reach an output, with constant results folded.
+#[derive(Clone, Debug)]
+struct Compiled {
+    ops: Vec<(Ins, Operand, Operand)>,
+    outputs: Vec<Operand>,
+    // The program this was compiled from.
+    program: Vec<Op>,
+}
+
+impl Compiled {
+    fn new(program: &[Op]) -> Self {
+        let live = live(program);
+        // Where the result of each instruction ends up.
+        let mut results = Vec::with_capacity(program.len());
+        let mut ops = Vec::new();
+        for (i, op) in program.iter().enumerate() {
+            if !live[i] {
+                results.push(Operand::Const(0.0));
+                continue;
+            }
+            let a = resolve(op.first, &results);
+            let b = if op.ins.arity() == 2 {
+                resolve(op.second, &results)
+            } else {
+                Operand::Const(0.0)
+            };
+            results.push(match (op.ins, a, b) {
+                (Ins::_NOP, a, _) => a,
+                (ins, Operand::Const(a), Operand::Const(b)) => {
+                    Operand::Const(processor(&ins, a, b))
+                }
+                (ins, a, b) => {
+                    ops.push((ins, a, b));
+                    Operand::Slot(ops.len() - 1)
+                }
+            });
+        }
+        Compiled {
+            ops: ops,
+            outputs: (0..TOTAL_OUTPUTS)
+                .map(|o| results[output_instruction(program.len(), o)])
+                .collect_vec(),
+            program: program.to_vec(),
+        }
+    }
+
+    fn evaluate(&self, inputs: &[f64], buffer: &mut Vec<f64>, outputs: &mut [f64]) {
+        buffer.clear();
+        for &(ins, a, b) in &self.ops {
+            let v = processor(&ins, fetch(a, inputs, buffer), fetch(b, inputs, buffer));
+            buffer.push(v);
+        }
+        for (o, &operand) in outputs.iter_mut().zip(&self.outputs) {
+            *o = fetch(operand, inputs, buffer);
+        }
+    }
+}
+
+fn resolve(operand: usize, results: &[Operand]) -> Operand {
+    if operand < CONST_INPUTS {
+        Operand::Const(CONST_VALUES[operand])
+    } else if operand < TOTAL_INPUTS {
+        Operand::Input(operand)
+    } else {
+        results[operand - TOTAL_INPUTS]
+    }
+}
+
+#[inline]
+fn fetch(operand: Operand, inputs: &[f64], buffer: &[f64]) -> f64 {
+    match operand {
+        Operand::Input(i) => inputs[i],
+        Operand::Const(v) => v,
+        Operand::Slot(i) => buffer[i],
+    }
+}
+
 fn processor(ins: &Ins, a: f64, b: f64) -> f64 {
     match *ins {
         Ins::_NOP => a,
@@ -86,13 +354,27 @@ fn processor(ins: &Ins, a: f64, b: f64) -> f64 {
         Ins::_LN => a.ln(),
         Ins::_SIN => a.sin(),
         Ins::_SQT => a.sqrt(),
+        Ins::_ABS => a.abs(),
+        Ins::_MIN => a.min(b),
+        Ins::_MAX => a.max(b),
+        Ins::_TNH => a.tanh(),
+        Ins::_NEG => -a,
+        Ins::_SEL => {
+            if a >= 1.0 {
+                b
+            } else {
+                0.0
+            }
+        }
+        Ins::_COS => a.cos(),
         Ins::MAX => unreachable!(),
     }
 }
 
-fn mutator(ins: &mut Ins, rng: &mut Isaac64Rng) {
-    use std::mem;
-    *ins = unsafe { mem::transmute(rng.gen_range::<u8>(0, Ins::MAX as u8)) };
+/// The `Mep` type needs a mutator, but it can't see the rules, so it can't respect the instruction
+/// set. All mutation goes through `mutate_program` instead, so this is never called.
+fn mutator(_: &mut Ins, _: &mut Isaac64Rng) {
+    unreachable!()
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -100,59 +382,296 @@ pub struct Brain {
     #[serde(serialize_with = "mep_serializer", deserialize_with = "mep_deserializer")]
     pub mep: MepType,
     pub memory: [f64; TOTAL_MEMORY],
+    /// How many of the memory registers the brain can use.
+    pub memory_size: usize,
+    #[serde(skip_serializing, skip_deserializing)]
+    compiled: Option<Compiled>,
+    #[serde(skip_serializing, skip_deserializing)]
+    buffer: Vec<f64>,
 }
 
 impl Brain {
-    pub fn new(rng: &mut Isaac64Rng) -> Self {
-        let v = (0..DEFAULT_INSTRUCTIONS)
-            .map(|_| {
-                let mut ins = Ins::_NOP;
-                mutator(&mut ins, rng);
-                ins
-            })
-            .collect_vec();
+    pub fn new(instructions: &InstructionSet, rng: &mut Isaac64Rng) -> Self {
+        let v = (0..DEFAULT_INSTRUCTIONS).map(|_| instructions.sample(rng)).collect_vec();
+        let mut b = Brain::from_mep(mli::Mep::new(TOTAL_INPUTS,
+                                                  TOTAL_OUTPUTS,
+                                                  DEFAULT_MUTATE_SIZE,
+                                                  DEFAULT_CROSSOVER_POINTS,
+                                                  rng,
+                                                  v.into_iter(),
+                                                  mutator,
+                                                  processor),
+                                    DEFAULT_MEMORY);
+        b.recompile();
+        b
+    }
+
+    /// Builds a brain without compiling it. Callers must then recompile it or set its program.
+    fn from_mep(mep: MepType, memory_size: usize) -> Self {
         Brain {
-            mep: mli::Mep::new(TOTAL_INPUTS,
-                               TOTAL_OUTPUTS,
-                               DEFAULT_MUTATE_SIZE,
-                               DEFAULT_CROSSOVER_POINTS,
-                               rng,
-                               v.into_iter(),
-                               mutator,
-                               processor),
+            mep: mep,
             memory: [0.0; TOTAL_MEMORY],
+            memory_size: memory_size,
+            compiled: None,
+            buffer: Vec::new(),
         }
     }
 
-    pub fn mutate(&mut self, rng: &mut Isaac64Rng) {
-        use mli::Genetic;
-        if rng.gen_range(0.0, 1.0) < MUTATE_PROBABILITY {
-            self.mep.mutate(rng);
+    fn recompile(&mut self) {
+        self.compiled = Some(Compiled::new(&self.program()));
+    }
+
+    pub fn program(&self) -> Vec<Op> {
+        mli::SerialMep::from(&self.mep)
+            .program
+            .into_iter()
+            .map(|o| {
+                Op {
+                    ins: o.instruction,
+                    first: o.first,
+                    second: o.second,
+                }
+            })
+            .collect_vec()
+    }
+
+    fn set_program(&mut self, program: Vec<Op>) {
+        let mut smep = mli::SerialMep::from(&self.mep);
+        smep.program = program.into_iter()
+            .map(|op| {
+                mli::Opcode {
+                    instruction: op.ins,
+                    first: op.first,
+                    second: op.second,
+                }
+            })
+            .collect_vec();
+        self.mep = MepType::new_from_serial_mep(smep, mutator, processor);
+        self.recompile();
+    }
+
+    /// Performs as many unit mutations as the genes call for. Each unit mutation changes the
+    /// instruction or one operand of a random instruction. New instructions are drawn from the
+    /// instruction set. Offspring may also gain or lose an instruction or a memory register.
+    ///
+    /// The mutated program replaces the brain's own, so the brain is only compiled once.
+    fn mutate_program(&mut self,
+                      mut program: Vec<Op>,
+                      genes: &Genes,
+                      rules: &Rules,
+                      rng: &mut Isaac64Rng) {
+        if genes.roll(rng) {
+            for _ in 0..genes.mutate_size {
+                let i = rng.gen_range(0, program.len());
+                match rng.gen_range(0, 3) {
+                    0 => program[i].ins = rules.instruction_set.sample(rng),
+                    1 => program[i].first = rng.gen_range(0, TOTAL_INPUTS + i),
+                    _ => program[i].second = rng.gen_range(0, TOTAL_INPUTS + i),
+                }
+            }
+        }
+        if program.len() < MAX_INSTRUCTIONS && rng.next_f64() < INSERT_PROBABILITY {
+            let i = rng.gen_range(0, program.len() + 1);
+            let op = Op {
+                ins: rules.instruction_set.sample(rng),
+                first: rng.gen_range(0, TOTAL_INPUTS + i),
+                second: rng.gen_range(0, TOTAL_INPUTS + i),
+            };
+            insert(&mut program, i, op);
+        }
+        if program.len() > MIN_INSTRUCTIONS && rng.next_f64() < DELETE_PROBABILITY {
+            let i = rng.gen_range(0, program.len());
+            remove(&mut program, i);
         }
+        if rng.next_f64() < MEMORY_MUTATE_PROBABILITY {
+            self.memory_size = if rng.gen() {
+                min(self.memory_size + 1, TOTAL_MEMORY)
+            } else {
+                self.memory_size.saturating_sub(1)
+            };
+        }
+        self.set_program(program);
     }
 
-    pub fn mate(&self, other: &Self, rng: &mut Isaac64Rng) -> Self {
-        use mli::Genetic;
-        let mut b = Brain {
-            mep: Genetic::mate((&self.mep, &other.mep), rng),
-            memory: [0.0; TOTAL_MEMORY],
+    /// Surrounds the sensors with the constants and the current memory.
+    pub fn full_inputs(&self, sensors: &[f64]) -> Vec<f64> {
+        full_inputs(sensors, &self.memory[..])
+    }
+
+    /// Evaluates the brain using its compiled form, which is only rebuilt when the program changes.
+    pub fn compute(&mut self, inputs: &[f64]) -> [f64; TOTAL_OUTPUTS] {
+        if self.compiled.is_none() {
+            // Brains that were just deserialized have not been compiled yet.
+            self.recompile();
+        }
+        let mut outputs = [0.0; TOTAL_OUTPUTS];
+        self.compiled.as_ref().unwrap().evaluate(inputs, &mut self.buffer, &mut outputs);
+        outputs
+    }
+}
+
+impl Controller for Brain {
+    fn inputs(&self) -> usize {
+        STATIC_INPUTS
+    }
+
+    fn outputs(&self) -> usize {
+        STATIC_OUTPUTS
+    }
+
+    /// Surrounds the sensors with the constants and memory and stores the memory outputs.
+    fn decide(&mut self, sensors: &[f64], actions: &mut [f64]) {
+        let inputs = self.full_inputs(sensors);
+        let outputs = self.compute(&inputs[..]);
+        actions.copy_from_slice(&outputs[..STATIC_OUTPUTS]);
+        // Registers the brain hasn't evolved always read zero.
+        for (i, (m, &o)) in self.memory.iter_mut().zip(&outputs[STATIC_OUTPUTS..]).enumerate() {
+            *m = if i < self.memory_size { o } else { 0.0 };
+        }
+    }
+
+    fn reset(&mut self) {
+        self.memory = [0.0; TOTAL_MEMORY];
+    }
+
+    fn prepare(&mut self) {
+        if self.compiled.is_none() {
+            self.recompile();
+        }
+    }
+
+    /// Splices a random segment of the donor's program into the same place in this one.
+    ///
+    /// This uses the same `crossover` as `mate` rather than `mli::Genetic`, since programs can
+    /// differ in length and `crossover` keeps every instruction at its position so its operands
+    /// stay valid.
+    fn transfer(&mut self, donor: &Self, rng: &mut Isaac64Rng) {
+        let program = crossover(&self.program(), &donor.program(), 2, rng);
+        self.set_program(program);
+    }
+
+    /// The fraction of instructions, including operands, that are the same at the same position.
+    fn similarity(&self, other: &Self) -> f64 {
+        match (self.compiled.as_ref(), other.compiled.as_ref()) {
+            (Some(a), Some(b)) => {
+                let same = a.program.iter().zip(&b.program).filter(|&(x, y)| x == y).count();
+                same as f64 / max(a.program.len(), b.program.len()) as f64
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn memory(&self) -> &[f64] {
+        &self.memory[..self.memory_size]
+    }
+
+    fn memory_mut(&mut self) -> &mut [f64] {
+        &mut self.memory[..self.memory_size]
+    }
+
+    fn instructions(&self) -> usize {
+        self.compiled.as_ref().map(|c| c.program.len()).unwrap_or_else(|| self.program().len())
+    }
+
+    fn registers(&self) -> usize {
+        self.memory_size
+    }
+
+    /// Mutates the brain's own program as described on `mutate_program`.
+    fn mutate(&mut self, genes: &Genes, rules: &Rules, rng: &mut Isaac64Rng) {
+        let program = self.program();
+        self.mutate_program(program, genes, rules, rng);
+    }
+
+    fn mate(&self, other: &Self, genes: &Genes, rules: &Rules, rng: &mut Isaac64Rng) -> Self {
+        let program = crossover(&self.program(), &other.program(), genes.crossover_points, rng);
+        let memory_size = if rng.gen() {
+            self.memory_size
+        } else {
+            other.memory_size
         };
+        let mut b = Brain::from_mep(self.mep.clone(), memory_size);
         // Perform unit mutations on offspring
-        b.mutate(rng);
+        b.mutate_program(program, genes, rules, rng);
         b
     }
 
-    pub fn divide(&self, rng: &mut Isaac64Rng) -> Self {
-        let mut b = Brain {
-            mep: self.mep.clone(),
-            memory: [0.0; TOTAL_MEMORY],
-        };
+    fn divide(&self, genes: &Genes, rules: &Rules, rng: &mut Isaac64Rng) -> Self {
+        let mut b = Brain::from_mep(self.mep.clone(), self.memory_size);
         // Perform unit mutations on offspring
-        b.mutate(rng);
+        b.mutate(genes, rules, rng);
         b
     }
 }
 
+/// Lays out every input of a brain, from the constants through the sensors to the memory.
+/// Registers missing from `memory` read zero.
+pub fn full_inputs(sensors: &[f64], memory: &[f64]) -> Vec<f64> {
+    let mut inputs = Vec::with_capacity(TOTAL_INPUTS);
+    inputs.extend_from_slice(&CONST_VALUES[..]);
+    inputs.extend_from_slice(sensors);
+    inputs.extend_from_slice(memory);
+    inputs.resize(TOTAL_INPUTS, 0.0);
+    inputs
+}
+
+/// Inserts an instruction and renumbers the operands that refer to instructions after it.
+fn insert(program: &mut Vec<Op>, i: usize, op: Op) {
+    program.insert(i, op);
+    for op in &mut program[i + 1..] {
+        if op.first >= TOTAL_INPUTS + i {
+            op.first += 1;
+        }
+        if op.second >= TOTAL_INPUTS + i {
+            op.second += 1;
+        }
+    }
+}
+
+/// Removes an instruction. Operands that used its result use its first operand instead.
+fn remove(program: &mut Vec<Op>, i: usize) {
+    let removed = program.remove(i);
+    let renumber = |operand: usize| if operand == TOTAL_INPUTS + i {
+        removed.first
+    } else if operand > TOTAL_INPUTS + i {
+        operand - 1
+    } else {
+        operand
+    };
+    for op in &mut program[i..] {
+        op.first = renumber(op.first);
+        op.second = renumber(op.second);
+    }
+}
+
+/// Crosses two programs, switching parents at each crossover point.
+///
+/// Instructions keep their position so their operands stay valid. The offspring has the length of
+/// the parent its program ends with, since that parent provides the outputs.
+fn crossover(a: &[Op], b: &[Op], points: usize, rng: &mut Isaac64Rng) -> Vec<Op> {
+    let shortest = min(a.len(), b.len());
+    let mut cuts = (0..points).map(|_| rng.gen_range(0, shortest)).collect_vec();
+    cuts.sort();
+    let mut program = Vec::with_capacity(max(a.len(), b.len()));
+    let mut from_a = true;
+    let mut start = 0;
+    for cut in cuts {
+        program.extend_from_slice(if from_a {
+            &a[start..cut]
+        } else {
+            &b[start..cut]
+        });
+        from_a = !from_a;
+        start = cut;
+    }
+    program.extend_from_slice(if from_a {
+        &a[start..]
+    } else {
+        &b[start..]
+    });
+    program
+}
+
 fn mep_serializer<S>(mep: &MepType, serializer: &mut S) -> Result<(), S::Error>
     where S: Serializer
 {
@@ -166,3 +685,117 @@ fn mep_deserializer<D>(deserializer: &mut D) -> Result<MepType, D::Error>
     let smep = try!(mli::SerialMep::deserialize(deserializer));
     Ok(MepType::new_from_serial_mep(smep, mutator, processor))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Isaac64Rng, Rng, SeedableRng};
+
+    fn op(first: usize, second: usize) -> Op {
+        Op {
+            ins: Ins::_ADD,
+            first: first,
+            second: second,
+        }
+    }
+
+    #[test]
+    fn insert_renumbers_later_results() {
+        let t = TOTAL_INPUTS;
+        let mut program = vec![op(0, 1), op(t, 2), op(t, t + 1)];
+        insert(&mut program, 1, op(3, t));
+        assert_eq!(program, vec![op(0, 1), op(3, t), op(t, 2), op(t, t + 2)]);
+    }
+
+    #[test]
+    fn remove_renumbers_later_results() {
+        let t = TOTAL_INPUTS;
+        let mut program = vec![op(0, 1), op(4, t), op(t + 1, 2), op(t, t + 2)];
+        remove(&mut program, 1);
+        // Uses of the removed result fall back to its first operand.
+        assert_eq!(program, vec![op(0, 1), op(4, 2), op(t, t + 1)]);
+    }
+
+    #[test]
+    fn insert_then_remove_restores_program() {
+        let t = TOTAL_INPUTS;
+        let original = vec![op(0, 1), op(t, 2), op(t, t + 1), op(t + 2, t + 1)];
+        let mut program = original.clone();
+        insert(&mut program, 2, op(5, t + 1));
+        remove(&mut program, 2);
+        assert_eq!(program, original);
+    }
+
+    #[test]
+    fn crossover_stays_within_bounds() {
+        let mut rng = Isaac64Rng::from_seed(&[1, 2, 3, 4]);
+        let random = |len: usize, rng: &mut Isaac64Rng| {
+            (0..len)
+                .map(|i| {
+                    let first = rng.gen_range(0, TOTAL_INPUTS + i);
+                    op(first, rng.gen_range(0, TOTAL_INPUTS + i))
+                })
+                .collect::<Vec<_>>()
+        };
+        for _ in 0..100 {
+            let a_len = rng.gen_range(MIN_INSTRUCTIONS, MAX_INSTRUCTIONS + 1);
+            let b_len = rng.gen_range(MIN_INSTRUCTIONS, MAX_INSTRUCTIONS + 1);
+            let a = random(a_len, &mut rng);
+            let b = random(b_len, &mut rng);
+            let points = rng.gen_range(1, 4);
+            let child = crossover(&a, &b, points, &mut rng);
+            assert!(child.len() >= MIN_INSTRUCTIONS && child.len() <= MAX_INSTRUCTIONS);
+            assert!(child.len() == a.len() || child.len() == b.len());
+            // Every operand must still refer to an input or an earlier instruction.
+            for (i, o) in child.iter().enumerate() {
+                assert!(o.first < TOTAL_INPUTS + i && o.second < TOTAL_INPUTS + i);
+            }
+        }
+    }
+
+    #[test]
+    fn compiled_matches_mep() {
+        use std::mem;
+        let mut rng = Isaac64Rng::from_seed(&[4, 3, 2, 1]);
+        let mut brain = Brain::new(&InstructionSet::new(), &mut rng);
+        let (mut dead, mut folded) = (false, false);
+        for _ in 0..100 {
+            let len = rng.gen_range(MIN_INSTRUCTIONS, 2 * MIN_INSTRUCTIONS);
+            // Half of the operands are constants so that whole subtrees can be folded.
+            let program = (0..len)
+                .map(|i| {
+                    let operand = |rng: &mut Isaac64Rng| if rng.gen() {
+                        rng.gen_range(0, CONST_INPUTS)
+                    } else {
+                        rng.gen_range(0, TOTAL_INPUTS + i)
+                    };
+                    Op {
+                        ins: unsafe { mem::transmute(rng.gen_range::<u8>(0, Ins::MAX as u8)) },
+                        first: operand(&mut rng),
+                        second: operand(&mut rng),
+                    }
+                })
+                .collect::<Vec<_>>();
+            let live = live(&program);
+            dead |= live.iter().any(|&l| !l);
+            brain.set_program(program);
+            folded |= brain.compiled.as_ref().unwrap().ops.len() <
+                      live.iter().filter(|&&l| l).count();
+
+            let sensors = (0..STATIC_INPUTS).map(|_| rng.gen_range(-2.0, 2.0)).collect::<Vec<_>>();
+            let memory = (0..TOTAL_MEMORY).map(|_| rng.gen_range(-2.0, 2.0)).collect::<Vec<_>>();
+            let inputs = full_inputs(&sensors[..], &memory[..]);
+            let expected = brain.mep.compute(&inputs[..]).collect::<Vec<_>>();
+            let actual = brain.compute(&inputs[..]);
+            assert_eq!(expected.len(), TOTAL_OUTPUTS);
+            for (o, (&e, &a)) in expected.iter().zip(actual.iter()).enumerate() {
+                assert!(e == a || (e.is_nan() && a.is_nan()),
+                        "output {} is {} but the Mep gives {}",
+                        o,
+                        a,
+                        e);
+            }
+        }
+        assert!(dead && folded);
+    }
+}
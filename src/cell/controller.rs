@@ -0,0 +1,176 @@
+use rand::Isaac64Rng;
+use super::Rules;
+use super::brain::Brain;
+use super::genes::Genes;
+use super::neural::Network;
+
+/// Something that turns a cell's senses into actions.
+///
+/// Senses and actions follow the layout used by `Cell::decide`. Any internal state, such as memory,
+/// is kept by the controller itself.
+pub trait Controller {
+    /// The number of sensor values given to `decide`.
+    fn inputs(&self) -> usize;
+    /// The number of action values produced by `decide`.
+    fn outputs(&self) -> usize;
+    fn decide(&mut self, sensors: &[f64], actions: &mut [f64]);
+    /// The number of instructions in the genome, for charging complexity costs.
+    fn instructions(&self) -> usize;
+    /// The number of memory registers in use, for charging complexity costs.
+    fn registers(&self) -> usize;
+    /// Clears any internal state.
+    fn reset(&mut self);
+    /// Builds anything that neighbors need to read while other cells are deciding.
+    fn prepare(&mut self);
+    /// Takes part of the donor's genome, as in horizontal gene transfer.
+    fn transfer(&mut self, donor: &Self, rng: &mut Isaac64Rng) where Self: Sized;
+    /// How genetically alike two controllers are, from 0 to 1.
+    fn similarity(&self, other: &Self) -> f64 where Self: Sized;
+    /// The internal state that offspring can inherit.
+    fn memory(&self) -> &[f64];
+    fn memory_mut(&mut self) -> &mut [f64];
+    /// Mutates the controller as much as the genes of the cell it belongs to call for.
+    fn mutate(&mut self, genes: &Genes, rules: &Rules, rng: &mut Isaac64Rng);
+    /// `genes` are those of the offspring, which decide how it is crossed and mutated.
+    fn mate(&self, other: &Self, genes: &Genes, rules: &Rules, rng: &mut Isaac64Rng) -> Self
+        where Self: Sized;
+    fn divide(&self, genes: &Genes, rules: &Rules, rng: &mut Isaac64Rng) -> Self where Self: Sized;
+}
+
+/// Which kind of controller new cells are created with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControllerKind {
+    Mep,
+    Neural,
+}
+
+/// Any controller, so that cells with different controllers can share a grid and be saved.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Mind {
+    Mep(Brain),
+    Neural(Network),
+}
+
+impl Mind {
+    pub fn new(kind: ControllerKind, rules: &Rules, rng: &mut Isaac64Rng) -> Self {
+        match kind {
+            ControllerKind::Mep => Mind::Mep(Brain::new(&rules.instruction_set, rng)),
+            ControllerKind::Neural => Mind::Neural(Network::new(rng)),
+        }
+    }
+
+    pub fn kind(&self) -> ControllerKind {
+        match *self {
+            Mind::Mep(_) => ControllerKind::Mep,
+            Mind::Neural(_) => ControllerKind::Neural,
+        }
+    }
+}
+
+impl Controller for Mind {
+    fn inputs(&self) -> usize {
+        match *self {
+            Mind::Mep(ref b) => b.inputs(),
+            Mind::Neural(ref n) => n.inputs(),
+        }
+    }
+
+    fn outputs(&self) -> usize {
+        match *self {
+            Mind::Mep(ref b) => b.outputs(),
+            Mind::Neural(ref n) => n.outputs(),
+        }
+    }
+
+    fn decide(&mut self, sensors: &[f64], actions: &mut [f64]) {
+        match *self {
+            Mind::Mep(ref mut b) => b.decide(sensors, actions),
+            Mind::Neural(ref mut n) => n.decide(sensors, actions),
+        }
+    }
+
+    fn instructions(&self) -> usize {
+        match *self {
+            Mind::Mep(ref b) => b.instructions(),
+            Mind::Neural(ref n) => n.instructions(),
+        }
+    }
+
+    fn registers(&self) -> usize {
+        match *self {
+            Mind::Mep(ref b) => b.registers(),
+            Mind::Neural(ref n) => n.registers(),
+        }
+    }
+
+    fn reset(&mut self) {
+        match *self {
+            Mind::Mep(ref mut b) => b.reset(),
+            Mind::Neural(ref mut n) => n.reset(),
+        }
+    }
+
+    fn prepare(&mut self) {
+        match *self {
+            Mind::Mep(ref mut b) => b.prepare(),
+            Mind::Neural(ref mut n) => n.prepare(),
+        }
+    }
+
+    /// Genes can't be transferred between controllers of different kinds.
+    fn transfer(&mut self, donor: &Self, rng: &mut Isaac64Rng) {
+        match (self, donor) {
+            (&mut Mind::Mep(ref mut a), &Mind::Mep(ref b)) => a.transfer(b, rng),
+            (&mut Mind::Neural(ref mut a), &Mind::Neural(ref b)) => a.transfer(b, rng),
+            _ => {}
+        }
+    }
+
+    /// Controllers of different kinds have nothing in common.
+    fn similarity(&self, other: &Self) -> f64 {
+        match (self, other) {
+            (&Mind::Mep(ref a), &Mind::Mep(ref b)) => a.similarity(b),
+            (&Mind::Neural(ref a), &Mind::Neural(ref b)) => a.similarity(b),
+            _ => 0.0,
+        }
+    }
+
+    fn memory(&self) -> &[f64] {
+        match *self {
+            Mind::Mep(ref b) => b.memory(),
+            Mind::Neural(ref n) => n.memory(),
+        }
+    }
+
+    fn memory_mut(&mut self) -> &mut [f64] {
+        match *self {
+            Mind::Mep(ref mut b) => b.memory_mut(),
+            Mind::Neural(ref mut n) => n.memory_mut(),
+        }
+    }
+
+    fn mutate(&mut self, genes: &Genes, rules: &Rules, rng: &mut Isaac64Rng) {
+        match *self {
+            Mind::Mep(ref mut b) => b.mutate(genes, rules, rng),
+            Mind::Neural(ref mut n) => n.mutate(genes, rules, rng),
+        }
+    }
+
+    /// Controllers of different kinds can't be crossed, so the offspring is a copy of this one.
+    fn mate(&self, other: &Self, genes: &Genes, rules: &Rules, rng: &mut Isaac64Rng) -> Self {
+        match (self, other) {
+            (&Mind::Mep(ref a), &Mind::Mep(ref b)) => Mind::Mep(a.mate(b, genes, rules, rng)),
+            (&Mind::Neural(ref a), &Mind::Neural(ref b)) => {
+                Mind::Neural(a.mate(b, genes, rules, rng))
+            }
+            _ => self.divide(genes, rules, rng),
+        }
+    }
+
+    fn divide(&self, genes: &Genes, rules: &Rules, rng: &mut Isaac64Rng) -> Self {
+        match *self {
+            Mind::Mep(ref b) => Mind::Mep(b.divide(genes, rules, rng)),
+            Mind::Neural(ref n) => Mind::Neural(n.divide(genes, rules, rng)),
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Renders brains in a human-readable form using the input and output layout of `Cell::decide`.
+//!
+//! Neighbor directions of inputs and of the move, mate, spawn and turn outputs are relative to the
+//! way the cell is facing, while diffusion coefficients use absolute directions.
+
+use super::brain::{self, Brain, Ins, Op, BIRTH_MESSAGE, CONST_INPUTS, CONTACT_MESSAGE,
+                   NEIGHBOR_SENSES, TOTAL_INPUTS, TOTAL_MEMORY, TOTAL_OUTPUTS};
+use super::DIRECTIONS;
+use super::controller::Mind;
+use super::super::fluid::{FLUID_NAMES, PUMPED_FLUIDS, TOTAL_FLUIDS, TOTAL_PUMPED};
+
+const CONST_LABELS: [&'static str; CONST_INPUTS] = ["0.0", "0.5", "-0.5", "1.0", "-1.0", "2.0",
+                                                    "-2.0", "MAX", "MIN"];
+
+pub fn input_label(input: usize) -> String {
+    let mut i = input;
+    if i < CONST_INPUTS {
+        return CONST_LABELS[i].to_string();
+    }
+    i -= CONST_INPUTS;
+    if i == 0 {
+        return "inhale".to_string();
+    }
+    i -= 1;
+    if i < TOTAL_FLUIDS {
+        return format!("fluid[Center][{}]", FLUID_NAMES[i]);
+    }
+    i -= TOTAL_FLUIDS;
+    if i < 6 * TOTAL_FLUIDS {
+        return format!("fluid[{:?}][{}]",
+                       DIRECTIONS[i / TOTAL_FLUIDS],
+                       FLUID_NAMES[i % TOTAL_FLUIDS]);
+    }
+    i -= 6 * TOTAL_FLUIDS;
+    if i < 6 {
+        return format!("cell[{:?}]", DIRECTIONS[i]);
+    }
+    i -= 6;
+    if i < 6 * NEIGHBOR_SENSES {
+        let sense = ["inhale", "kinship", "facing", "choice"][i / 6];
+        return format!("{}[{:?}]", sense, DIRECTIONS[i % 6]);
+    }
+    i -= 6 * NEIGHBOR_SENSES;
+    if i < 6 * TOTAL_FLUIDS {
+        return format!("distant[{:?}][{}]",
+                       DIRECTIONS[i / TOTAL_FLUIDS],
+                       FLUID_NAMES[i % TOTAL_FLUIDS]);
+    }
+    i -= 6 * TOTAL_FLUIDS;
+    if i < 6 {
+        return format!("distant_cells[{:?}]", DIRECTIONS[i]);
+    }
+    i -= 6;
+    if i < 6 * CONTACT_MESSAGE {
+        return format!("received[{:?}][{}]",
+                       DIRECTIONS[i / CONTACT_MESSAGE],
+                       i % CONTACT_MESSAGE);
+    }
+    i -= 6 * CONTACT_MESSAGE;
+    if i == 0 {
+        return "age".to_string();
+    }
+    i -= 1;
+    if i < 6 {
+        return format!("wall[{:?}]", DIRECTIONS[i]);
+    }
+    i -= 6;
+    if i < TOTAL_PUMPED {
+        return format!("stored[{}]", FLUID_NAMES[PUMPED_FLUIDS[i]]);
+    }
+    i -= TOTAL_PUMPED;
+    if i < TOTAL_MEMORY {
+        return format!("memory[{}]", i);
+    }
+    panic!("Input {} is out of range.", input)
+}
+
+/// Labels the state a controller reads back as input on every decision, which is its memory
+/// registers for brains and its hidden layer for networks.
+pub fn memory_labels(mind: &Mind) -> Vec<String> {
+    match *mind {
+        Mind::Mep(_) => (0..TOTAL_MEMORY).map(|i| format!("memory[{}]", i)).collect(),
+        Mind::Neural(ref n) => (0..n.hidden.len()).map(|i| format!("hidden[{}]", i)).collect(),
+    }
+}
+
+pub fn output_label(output: usize) -> String {
+    let mut i = output;
+    if i < 6 * TOTAL_FLUIDS {
+        return format!("coef[{:?}][{}]",
+                       DIRECTIONS[i / TOTAL_FLUIDS],
+                       FLUID_NAMES[i % TOTAL_FLUIDS]);
+    }
+    i -= 6 * TOTAL_FLUIDS;
+    // Each of these is an attempt followed by directions or a single value.
+    let groups: [(&'static str, Option<&'static str>); 7] = [("move_attempt", Some("move")),
+                                                             ("mate_attempt", Some("mate")),
+                                                             ("", Some("spawn")),
+                                                             ("divide_attempt", None),
+                                                             ("", Some("turn")),
+                                                             ("explode", None),
+                                                             ("suicide", None)];
+    for &(single, directions) in &groups {
+        if single != "" {
+            if i == 0 {
+                return single.to_string();
+            }
+            i -= 1;
+        }
+        if let Some(name) = directions {
+            if i < 6 {
+                return format!("{}[{:?}]", name, DIRECTIONS[i]);
+            }
+            i -= 6;
+        }
+    }
+    if i < BIRTH_MESSAGE {
+        return format!("birth_message[{}]", i);
+    }
+    i -= BIRTH_MESSAGE;
+    if i < 6 * CONTACT_MESSAGE {
+        return format!("send[{:?}][{}]", DIRECTIONS[i / CONTACT_MESSAGE], i % CONTACT_MESSAGE);
+    }
+    i -= 6 * CONTACT_MESSAGE;
+    if i == 0 {
+        return "transfer_attempt".to_string();
+    }
+    i -= 1;
+    if i < 6 {
+        return format!("transfer[{:?}]", DIRECTIONS[i]);
+    }
+    i -= 6;
+    if i == 0 {
+        return "receptive".to_string();
+    }
+    i -= 1;
+    if i < TOTAL_PUMPED {
+        return format!("pump[{}]", FLUID_NAMES[PUMPED_FLUIDS[i]]);
+    }
+    i -= TOTAL_PUMPED;
+    for &name in &["build", "dig"] {
+        if i == 0 {
+            return format!("{}_attempt", name);
+        }
+        i -= 1;
+        if i < 6 {
+            return format!("{}[{:?}]", name, DIRECTIONS[i]);
+        }
+        i -= 6;
+    }
+    if i < TOTAL_MEMORY {
+        return format!("memory[{}]", i);
+    }
+    panic!("Output {} is out of range.", output)
+}
+
+fn render(ins: Ins, a: String, b: String) -> String {
+    let infix = match ins {
+        Ins::_NOP => return a,
+        Ins::_ADD => "+",
+        Ins::_SUB => "-",
+        Ins::_MUL => "*",
+        Ins::_DIV => "/",
+        Ins::_MOD => "%",
+        Ins::_GRT => ">",
+        Ins::_LES => "<",
+        Ins::_AND => "&&",
+        Ins::_OR => "||",
+        _ => {
+            return if ins.arity() == 1 {
+                format!("{}({})", ins.name(), a)
+            } else {
+                format!("{}({}, {})", ins.name(), a, b)
+            }
+        }
+    };
+    format!("({} {} {})", a, infix, b)
+}
+
+/// Counts how many times each instruction's result is used by live instructions and outputs.
+pub fn uses(program: &[Op], live: &[bool]) -> Vec<usize> {
+    let mut uses = vec![0; program.len()];
+    for o in 0..TOTAL_OUTPUTS {
+        uses[brain::output_instruction(program.len(), o)] += 1;
+    }
+    for (op, _) in program.iter().zip(live).filter(|&(_, &l)| l) {
+        if op.first >= TOTAL_INPUTS {
+            uses[op.first - TOTAL_INPUTS] += 1;
+        }
+        if op.ins.arity() == 2 && op.second >= TOTAL_INPUTS {
+            uses[op.second - TOTAL_INPUTS] += 1;
+        }
+    }
+    uses
+}
+
+struct Renderer<'a> {
+    program: &'a [Op],
+    uses: Vec<usize>,
+}
+
+impl<'a> Renderer<'a> {
+    fn operand(&self, operand: usize) -> String {
+        if operand < TOTAL_INPUTS {
+            input_label(operand)
+        } else if self.uses[operand - TOTAL_INPUTS] > 1 {
+            format!("t{}", operand - TOTAL_INPUTS)
+        } else {
+            self.expression(operand - TOTAL_INPUTS)
+        }
+    }
+
+    fn expression(&self, instruction: usize) -> String {
+        let op = &self.program[instruction];
+        render(op.ins,
+               self.operand(op.first),
+               if op.ins.arity() == 2 {
+                   self.operand(op.second)
+               } else {
+                   String::new()
+               })
+    }
+}
+
+/// Renders the live part of a brain as one expression per output.
+///
+/// Results that are used in more than one place are given a name of the form `t<instruction>`.
+pub fn disassemble(brain: &Brain) -> String {
+    let program = brain.program();
+    let live = brain::live(&program);
+    let renderer = Renderer {
+        uses: uses(&program, &live),
+        program: &program,
+    };
+
+    let mut s = String::new();
+    for i in 0..program.len() {
+        if live[i] && renderer.uses[i] > 1 {
+            s.push_str(&format!("t{} = {}\n", i, renderer.expression(i)));
+        }
+    }
+    for o in 0..TOTAL_OUTPUTS {
+        let i = brain::output_instruction(program.len(), o);
+        s.push_str(&format!("{} = {}\n",
+                            output_label(o),
+                            if renderer.uses[i] > 1 {
+                                format!("t{}", i)
+                            } else {
+                                renderer.expression(i)
+                            }));
+    }
+    s
+}
+
+/// Renders the live dataflow graph of a brain in the Graphviz DOT format.
+///
+/// Instructions and the inputs they read become nodes and each edge carries an operand to the
+/// instruction or output that uses it.
+pub fn dot(brain: &Brain) -> String {
+    let program = brain.program();
+    let live = brain::live(&program);
+
+    let mut s = String::from("digraph brain {\n    rankdir=LR;\n");
+    let mut inputs = vec![false; TOTAL_INPUTS];
+    let node = |operand: usize| if operand < TOTAL_INPUTS {
+        format!("in{}", operand)
+    } else {
+        format!("t{}", operand - TOTAL_INPUTS)
+    };
+
+    for (i, op) in program.iter().enumerate().filter(|&(i, _)| live[i]) {
+        s.push_str(&format!("    t{} [label=\"{}\"];\n", i, op.ins.name()));
+        if op.ins.arity() == 1 {
+            s.push_str(&format!("    {} -> t{};\n", node(op.first), i));
+        } else {
+            s.push_str(&format!("    {} -> t{} [label=\"a\"];\n", node(op.first), i));
+            s.push_str(&format!("    {} -> t{} [label=\"b\"];\n", node(op.second), i));
+        }
+        if op.first < TOTAL_INPUTS {
+            inputs[op.first] = true;
+        }
+        if op.ins.arity() == 2 && op.second < TOTAL_INPUTS {
+            inputs[op.second] = true;
+        }
+    }
+
+    for (i, _) in inputs.iter().enumerate().filter(|&(_, &used)| used) {
+        s.push_str(&format!("    in{} [label=\"{}\", shape=box];\n", i, input_label(i)));
+    }
+
+    for o in 0..TOTAL_OUTPUTS {
+        s.push_str(&format!("    out{} [label=\"{}\", shape=doubleoctagon];\n",
+                            o,
+                            output_label(o)));
+        s.push_str(&format!("    t{} -> out{};\n",
+                            brain::output_instruction(program.len(), o),
+                            o));
+    }
+
+    s.push_str("}\n");
+    s
+}
@@ -0,0 +1,174 @@
+use rand::{Rng, Isaac64Rng};
+use super::brain::{DEFAULT_CROSSOVER_POINTS, DEFAULT_MUTATE_PROBABILITY, DEFAULT_MUTATE_SIZE};
+use super::super::fluid::{KILL_FLUID_LOWER_THRESHOLD, KILL_FLUID_NORMAL,
+                          KILL_FLUID_UPPER_THRESHOLD};
+
+/// The number of actions that arbitration chooses between, in the order `Cell::decide` gives
+/// their attempts.
+pub const ACTIONS: usize = 8;
+pub const DEFAULT_THRESHOLD: f64 = 1.0;
+const MAX_THRESHOLD: f64 = 10.0;
+const THRESHOLD_STEP: f64 = 0.1;
+const MIN_MUTATE_PROBABILITY: f64 = 0.01;
+const MAX_MUTATE_SIZE: usize = 64;
+const MAX_CROSSOVER_POINTS: usize = 8;
+// How far the mutation probability can drift in one generation.
+const MUTATE_PROBABILITY_STEP: f64 = 0.05;
+const DEFAULT_TEMPERATURE: f64 = 1.0;
+const MIN_TEMPERATURE: f64 = 0.01;
+const MAX_TEMPERATURE: f64 = 100.0;
+// The temperature is scaled by up to this factor, up or down, in one generation.
+const TEMPERATURE_STEP: f64 = 1.1;
+const DEFAULT_OFFSPRING_SHARE: f64 = 0.5;
+const MIN_OFFSPRING_SHARE: f64 = 0.05;
+const MAX_OFFSPRING_SHARE: f64 = 0.95;
+const OFFSPRING_SHARE_STEP: f64 = 0.05;
+// How far the ornament, preference and tolerance can drift in one generation.
+const MATE_CHOICE_STEP: f64 = 0.05;
+const MIN_TOLERANCE: f64 = 0.05;
+// How far each kill fluid bound can drift in one generation.
+const KILL_TOLERANCE_STEP: f64 = 0.0005;
+const MAX_KILL_UPPER: f64 = 1.0;
+
+/// Heritable genes of a cell that aren't part of its controller, including those that control how
+/// its controller is mutated so lineages can evolve their own evolvability.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Genes {
+    /// The chance that an offspring receives unit mutations at all.
+    pub mutate_probability: f64,
+    /// How many unit mutations an offspring receives.
+    pub mutate_size: usize,
+    pub crossover_points: usize,
+    /// How strong each attempt must be under threshold arbitration.
+    pub thresholds: [f64; ACTIONS],
+    /// How random choices are under softmax arbitration. Low temperatures approach argmax.
+    pub temperature: f64,
+    /// The fraction of its inhale a parent gives to offspring when the split is heritable.
+    pub offspring_share: f64,
+    /// Reduced modulo the number of mating types in the rules to get the cell's mating type.
+    pub mating_type: u32,
+    /// A trait in the range [0, 1] that potential mates judge.
+    pub ornament: f64,
+    /// The ornament this cell looks for in a mate.
+    pub preference: f64,
+    /// How far a mate's ornament can be from the preference and still be accepted.
+    pub tolerance: f64,
+    /// The cell dies if the kill fluid in its hex falls below this.
+    pub kill_lower: f64,
+    /// The cell dies if the kill fluid in its hex rises above this.
+    pub kill_upper: f64,
+}
+
+impl Genes {
+    /// New cells get a random mating type and accept any ornament.
+    pub fn new(rng: &mut Isaac64Rng) -> Self {
+        Genes {
+            mutate_probability: DEFAULT_MUTATE_PROBABILITY,
+            mutate_size: DEFAULT_MUTATE_SIZE,
+            crossover_points: DEFAULT_CROSSOVER_POINTS,
+            thresholds: [DEFAULT_THRESHOLD; ACTIONS],
+            temperature: DEFAULT_TEMPERATURE,
+            offspring_share: DEFAULT_OFFSPRING_SHARE,
+            mating_type: rng.gen(),
+            ornament: 0.5,
+            preference: 0.5,
+            tolerance: 1.0,
+            kill_lower: KILL_FLUID_LOWER_THRESHOLD,
+            kill_upper: KILL_FLUID_UPPER_THRESHOLD,
+        }
+    }
+
+    /// How wide a range of kill fluid the cell survives.
+    pub fn kill_tolerance(&self) -> f64 {
+        self.kill_upper - self.kill_lower
+    }
+
+    /// Decides whether an offspring should receive unit mutations.
+    pub fn roll(&self, rng: &mut Isaac64Rng) -> bool {
+        rng.gen_range(0.0, 1.0) < self.mutate_probability
+    }
+
+    /// The genes mutate themselves, staying within their bounds. Mating types never mutate.
+    pub fn mutate(&mut self, rng: &mut Isaac64Rng) {
+        self.mutate_probability = drift(self.mutate_probability,
+                                        MUTATE_PROBABILITY_STEP,
+                                        MIN_MUTATE_PROBABILITY,
+                                        1.0,
+                                        rng);
+        self.mutate_size = step(self.mutate_size, 1, MAX_MUTATE_SIZE, rng);
+        self.crossover_points = step(self.crossover_points, 1, MAX_CROSSOVER_POINTS, rng);
+        for t in &mut self.thresholds {
+            *t = drift(*t, THRESHOLD_STEP, 0.0, MAX_THRESHOLD, rng);
+        }
+        self.temperature = (self.temperature *
+                            TEMPERATURE_STEP.powf(rng.gen_range(-1.0, 1.0)))
+            .max(MIN_TEMPERATURE)
+            .min(MAX_TEMPERATURE);
+        self.offspring_share = drift(self.offspring_share,
+                                     OFFSPRING_SHARE_STEP,
+                                     MIN_OFFSPRING_SHARE,
+                                     MAX_OFFSPRING_SHARE,
+                                     rng);
+        self.ornament = drift(self.ornament, MATE_CHOICE_STEP, 0.0, 1.0, rng);
+        self.preference = drift(self.preference, MATE_CHOICE_STEP, 0.0, 1.0, rng);
+        self.tolerance = drift(self.tolerance, MATE_CHOICE_STEP, MIN_TOLERANCE, 1.0, rng);
+        self.kill_lower = drift(self.kill_lower, KILL_TOLERANCE_STEP, 0.0, KILL_FLUID_NORMAL, rng);
+        self.kill_upper = drift(self.kill_upper,
+                                KILL_TOLERANCE_STEP,
+                                KILL_FLUID_NORMAL,
+                                MAX_KILL_UPPER,
+                                rng);
+    }
+
+    /// Offspring take each gene from either parent and then mutate.
+    pub fn mate(&self, other: &Self, rng: &mut Isaac64Rng) -> Self {
+        let mut thresholds = [0.0; ACTIONS];
+        for (i, t) in thresholds.iter_mut().enumerate() {
+            *t = pick(self.thresholds[i], other.thresholds[i], rng);
+        }
+        let mut genes = Genes {
+            mutate_probability: pick(self.mutate_probability, other.mutate_probability, rng),
+            mutate_size: pick(self.mutate_size, other.mutate_size, rng),
+            crossover_points: pick(self.crossover_points, other.crossover_points, rng),
+            thresholds: thresholds,
+            temperature: pick(self.temperature, other.temperature, rng),
+            offspring_share: pick(self.offspring_share, other.offspring_share, rng),
+            mating_type: pick(self.mating_type, other.mating_type, rng),
+            ornament: pick(self.ornament, other.ornament, rng),
+            preference: pick(self.preference, other.preference, rng),
+            tolerance: pick(self.tolerance, other.tolerance, rng),
+            kill_lower: pick(self.kill_lower, other.kill_lower, rng),
+            kill_upper: pick(self.kill_upper, other.kill_upper, rng),
+        };
+        genes.mutate(rng);
+        genes
+    }
+
+    pub fn divide(&self, rng: &mut Isaac64Rng) -> Self {
+        let mut genes = self.clone();
+        genes.mutate(rng);
+        genes
+    }
+}
+
+fn pick<T>(a: T, b: T, rng: &mut Isaac64Rng) -> T {
+    if rng.gen() {
+        a
+    } else {
+        b
+    }
+}
+
+/// Moves a value by up to `step` in either direction, staying within the bounds.
+fn drift(v: f64, step: f64, min: f64, max: f64, rng: &mut Isaac64Rng) -> f64 {
+    (v + rng.gen_range(-step, step)).max(min).min(max)
+}
+
+/// Moves a value up or down by one or leaves it alone.
+fn step(v: usize, min: usize, max: usize, rng: &mut Isaac64Rng) -> usize {
+    match rng.gen_range(0, 3) {
+        0 if v > min => v - 1,
+        1 if v < max => v + 1,
+        _ => v,
+    }
+}
@@ -1,11 +1,18 @@
-mod brain;
+pub mod brain;
+pub mod controller;
+pub mod disasm;
+pub mod genes;
+pub mod neural;
+pub mod sensitivity;
 
 use rand::{Isaac64Rng, Rng};
-use mli::SISO;
-use itertools::Itertools;
-use super::fluid::{NORMAL_DIFFUSION, TOTAL_FLUIDS};
+use self::controller::{Controller, ControllerKind, Mind};
+use self::genes::{Genes, DEFAULT_THRESHOLD};
+use super::fluid::{NORMAL_DIFFUSION, PUMPED_FLUIDS, TOTAL_FLUIDS, TOTAL_PUMPED};
 
-const INITIAL_INHALE: usize = 2000;
+const INITIAL_INHALE: f64 = 2000.0;
+pub const DEFAULT_SENSE_RADIUS: usize = 1;
+pub const MAX_SENSE_RADIUS: usize = 5;
 
 enum_from_primitive! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -19,6 +26,13 @@ enum_from_primitive! {
     }
 }
 
+pub const DIRECTIONS: [Direction; 6] = [Direction::UpRight,
+                                        Direction::UpLeft,
+                                        Direction::Left,
+                                        Direction::DownLeft,
+                                        Direction::DownRight,
+                                        Direction::Right];
+
 impl Direction {
     pub fn delta(&self, even_y: bool) -> (isize, isize) {
         use self::Direction::*;
@@ -79,122 +93,449 @@ pub enum Choice {
     Move(Direction),
     Explode(bool),
     Suicide,
+    /// Give part of the genome to the neighbor in a direction.
+    Transfer(Direction),
+    /// Turn the empty hex in a direction into a wall.
+    Build(Direction),
+    /// Turn the wall in a direction back into an empty hex.
+    Dig(Direction),
     Nothing,
 }
 
+impl Choice {
+    /// A number that stands for the kind of choice, so neighbors can sense it.
+    pub fn code(&self) -> f64 {
+        match *self {
+            Choice::Nothing => 0.0,
+            Choice::Move(_) => 1.0,
+            Choice::Divide { mate, spawn } if mate == spawn => 2.0,
+            Choice::Divide { .. } => 3.0,
+            Choice::Explode(true) => 4.0,
+            Choice::Explode(false) => 5.0,
+            Choice::Suicide => 6.0,
+            Choice::Transfer(_) => 7.0,
+            Choice::Build(_) => 8.0,
+            Choice::Dig(_) => 9.0,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Decision {
     pub choice: Choice,
     pub coefficients: [[f64; TOTAL_FLUIDS]; 6],
+    /// How strongly the chosen action was attempted, from 0 to 1. The activity cost is charged in
+    /// proportion to it.
+    pub effort: f64,
+    /// Whether the cell accepts genes that neighbors voluntarily transfer to it.
+    pub receptive: bool,
+    /// How much of each pumped fluid to move, from -1 to 1. Positive values secrete a share of
+    /// what is stored and negative values absorb a share of what is in the hex.
+    pub pumps: [f64; TOTAL_PUMPED],
+}
+
+/// What offspring start with in their memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemoryInheritance {
+    Reset,
+    /// Copy the memory of the parent that reproduced.
+    Copy,
+    /// Average the memory of both parents, or copy it when dividing.
+    Average,
+    /// Use the birth message the parent output on its last decision.
+    Message,
+}
+
+/// Which optional senses cells have of their neighbors. Disabled senses always read 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Senses {
+    pub energy: bool,
+    /// How genetically alike the neighbor's brain is.
+    pub kinship: bool,
+    /// The way the neighbor is facing, relative to the cell.
+    pub facing: bool,
+    /// What the neighbor chose on its last decision.
+    pub choice: bool,
+}
+
+impl Senses {
+    pub fn none() -> Self {
+        Senses {
+            energy: false,
+            kinship: false,
+            facing: false,
+            choice: false,
+        }
+    }
+
+    pub fn all() -> Self {
+        Senses {
+            energy: true,
+            kinship: true,
+            facing: true,
+            choice: true,
+        }
+    }
+}
+
+/// Groups of sensors that can be ablated, so that they always read 0, to test which senses matter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Channel {
+    Energy,
+    /// Every fluid, both nearby and distant.
+    Fluids,
+    SignalFluids,
+    /// Whether neighbors are present.
+    Presence,
+    NeighborSenses,
+    Distant,
+    Messages,
+    Age,
+    /// Whether neighboring hexes are walls.
+    Walls,
+    /// How much of each pumped fluid the cell stores.
+    Stored,
+}
+
+pub const CHANNELS: [Channel; 10] = [Channel::Energy,
+                                     Channel::Fluids,
+                                     Channel::SignalFluids,
+                                     Channel::Presence,
+                                     Channel::NeighborSenses,
+                                     Channel::Distant,
+                                     Channel::Messages,
+                                     Channel::Age,
+                                     Channel::Walls,
+                                     Channel::Stored];
+
+impl Channel {
+    /// The indices of the channel's sensors in the layout built by `Cell::sense`.
+    pub fn sensors(&self) -> Vec<usize> {
+        use self::Channel::*;
+        let fluids = 1;
+        let presence = fluids + 7 * TOTAL_FLUIDS;
+        let neighbor = presence + 6;
+        let distant = neighbor + 6 * brain::NEIGHBOR_SENSES;
+        let messages = distant + brain::DISTANT_INPUTS;
+        let age = messages + 6 * brain::CONTACT_MESSAGE;
+        let walls = age + 1;
+        let stored = walls + 6;
+        let distant_fluids = distant..distant + 6 * TOTAL_FLUIDS;
+        match *self {
+            Energy => vec![0],
+            Fluids => (fluids..presence).chain(distant_fluids).collect(),
+            // The last four fluids are signals.
+            SignalFluids => {
+                (fluids..presence)
+                    .filter(|&i| (i - fluids) % TOTAL_FLUIDS >= 4)
+                    .chain(distant_fluids.filter(|&i| (i - distant) % TOTAL_FLUIDS >= 4))
+                    .collect()
+            }
+            Presence => (presence..neighbor).collect(),
+            NeighborSenses => (neighbor..distant).collect(),
+            Distant => (distant..messages).collect(),
+            Messages => (messages..age).collect(),
+            Age => vec![age],
+            Walls => (walls..stored).collect(),
+            Stored => (stored..stored + TOTAL_PUMPED).collect(),
+        }
+    }
+}
+
+/// How a cell chooses between moving, dividing, mating, exploding and suicide.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArbitrationPolicy {
+    /// Take the strongest attempt if it is positive.
+    Argmax,
+    /// Sample attempts using the cell's evolved temperature, with doing nothing as an attempt of 0.
+    Softmax,
+    /// Take the attempt that exceeds its own heritable threshold by the most, if any do.
+    Threshold,
+}
+
+/// How much of a parent's inhale goes to its offspring.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EnergySplit {
+    /// Every parent gives the same fraction.
+    Fixed(f64),
+    /// Each parent gives the fraction in its genes.
+    Heritable,
+}
+
+/// Whether cells can transfer genes to their neighbors and if the neighbors get a say in it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GeneTransfer {
+    Off,
+    /// Like a plasmid, genes are only taken by receptive neighbors.
+    Voluntary,
+    /// Like a virus, genes are forced on neighbors.
+    Forced,
+}
+
+/// How cells suffer as they get older.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Senescence {
+    Off,
+    /// Inhale charged each cycle for every cycle of age.
+    Cost(f64),
+    /// The chance of dying each cycle for every cycle of age.
+    Mortality(f64),
+}
+
+/// Where the range of kill fluid a cell survives comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KillBounds {
+    /// Every cell survives the same range.
+    Fixed,
+    /// Each cell survives the range in its genes and pays to maintain it.
+    Heritable,
+}
+
+/// Run parameters that govern how cells think and reproduce.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Rules {
+    pub controller: ControllerKind,
+    pub instruction_set: brain::InstructionSet,
+    pub memory_inheritance: MemoryInheritance,
+    pub senses: Senses,
+    /// How far cells can sense. Hexes beyond the neighbors, from a distance of 2 up to this, are
+    /// aggregated by sector.
+    pub sense_radius: usize,
+    pub ablated: Vec<Channel>,
+    pub arbitration: ArbitrationPolicy,
+    pub energy_split: EnergySplit,
+    /// With fewer than two mating types, any cells can mate.
+    pub mating_types: u32,
+    /// Whether cells only mate with partners whose ornament they prefer.
+    pub mate_choice: bool,
+    pub gene_transfer: GeneTransfer,
+    pub senescence: Senescence,
+    pub kill_bounds: KillBounds,
+    /// Whether cells pump fluids in and out of their storage.
+    pub pumping: bool,
+    /// Whether cells can build and dig walls.
+    pub terrain: bool,
+}
+
+impl Rules {
+    pub fn new() -> Self {
+        Rules {
+            controller: ControllerKind::Mep,
+            instruction_set: brain::InstructionSet::new(),
+            memory_inheritance: MemoryInheritance::Reset,
+            senses: Senses::none(),
+            sense_radius: DEFAULT_SENSE_RADIUS,
+            ablated: Vec::new(),
+            arbitration: ArbitrationPolicy::Argmax,
+            energy_split: EnergySplit::Fixed(0.5),
+            mating_types: 0,
+            mate_choice: false,
+            gene_transfer: GeneTransfer::Off,
+            senescence: Senescence::Off,
+            kill_bounds: KillBounds::Fixed,
+            pumping: false,
+            terrain: false,
+        }
+    }
+}
+
+/// What a cell senses of one sector beyond its neighbors.
+#[derive(Clone, Copy, Debug)]
+pub struct Distant {
+    /// The mean of each fluid.
+    pub fluids: [f64; TOTAL_FLUIDS],
+    /// The number of cells.
+    pub cells: f64,
+}
+
+impl Distant {
+    pub fn new() -> Self {
+        Distant {
+            fluids: [0.0; TOTAL_FLUIDS],
+            cells: 0.0,
+        }
+    }
+}
+
+/// What neighbors can see of a cell besides its energy and brain.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Appearance {
+    pub turn: usize,
+    pub choice: f64,
+    /// Messages sent towards each neighbor, by absolute direction.
+    pub messages: [[f64; brain::CONTACT_MESSAGE]; 6],
+}
+
+impl Appearance {
+    fn new(turn: usize) -> Self {
+        Appearance {
+            turn: turn,
+            choice: Choice::Nothing.code(),
+            messages: [[0.0; brain::CONTACT_MESSAGE]; 6],
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Cell {
-    pub inhale: usize,
+    /// Identifies the cell for as long as it lives, so it can be followed as it moves.
+    pub id: u64,
+    /// The number of cycles the cell has lived.
+    pub age: u64,
+    /// The energy the cell holds.
+    pub inhale: f64,
     pub suicide: bool,
-    pub brain: brain::Brain,
+    pub brain: Mind,
+    pub genes: Genes,
+    pub birth_message: [f64; brain::BIRTH_MESSAGE],
+    /// Fluids the cell has absorbed, in the order of `PUMPED_FLUIDS`.
+    pub stored: [f64; TOTAL_PUMPED],
     turn: usize,
+    /// What neighbors see this cycle. It is only updated by `publish` so that every cell decides
+    /// on the same view of its neighbors.
+    appearance: Appearance,
+    next_appearance: Appearance,
 }
 
 impl Cell {
-    pub fn new(rng: &mut Isaac64Rng) -> Self {
+    pub fn new(rules: &Rules, rng: &mut Isaac64Rng) -> Self {
+        let turn = rng.gen_range(0, 6);
         Cell {
             inhale: INITIAL_INHALE,
             suicide: false,
-            brain: brain::Brain::new(rng),
-            turn: rng.gen_range(0, 6),
+            birth_message: [0.0; brain::BIRTH_MESSAGE],
+            stored: [0.0; TOTAL_PUMPED],
+            brain: Mind::new(rules.controller, rules, rng),
+            genes: Genes::new(rng),
+            id: rng.gen(),
+            age: 0,
+            turn: turn,
+            appearance: Appearance::new(turn),
+            next_appearance: Appearance::new(turn),
+        }
+    }
+
+    /// Creates a cell around an existing brain and genes, such as ones taken from a library or
+    /// archive.
+    pub fn from_brain(mut brain: Mind, genes: Genes, rng: &mut Isaac64Rng) -> Self {
+        brain.reset();
+        let turn = rng.gen_range(0, 6);
+        Cell {
+            inhale: INITIAL_INHALE,
+            suicide: false,
+            birth_message: [0.0; brain::BIRTH_MESSAGE],
+            stored: [0.0; TOTAL_PUMPED],
+            brain: brain,
+            genes: genes,
+            id: rng.gen(),
+            age: 0,
+            turn: turn,
+            appearance: Appearance::new(turn),
+            next_appearance: Appearance::new(turn),
         }
     }
 
     pub fn color(&self) -> [f32; 4] {
-        [1.0, 1.0, 1.0, 1.0]
+        match self.brain {
+            Mind::Mep(_) => [1.0, 1.0, 1.0, 1.0],
+            Mind::Neural(_) => [0.6, 0.8, 1.0, 1.0],
+        }
+    }
+
+    /// Makes what the cell showed on its last decision visible to neighbors and prepares its brain
+    /// to be read by them. This must happen for every cell before any of them decide.
+    pub fn publish(&mut self) {
+        self.appearance = self.next_appearance.clone();
+        self.brain.prepare();
     }
 
-    pub fn decide(&mut self, fluids: [&[f64; TOTAL_FLUIDS]; 7], cells: &[bool; 6]) -> Decision {
-        use std::f64::{MAX, MIN};
+    /// Builds the sensors given to the brain, leaving out any ablated channels.
+    pub fn sense(&self,
+                 fluids: [&[f64; TOTAL_FLUIDS]; 7],
+                 neighbors: [Option<&Cell>; 6],
+                 distant: &[Distant; 6],
+                 walls: [bool; 6],
+                 rules: &Rules)
+                 -> Vec<f64> {
         let nc = |n: bool| if n {
             1.0
         } else {
             0.0
         };
-        let inputs = [0.0,
-                      0.5,
-                      -0.5,
-                      1.0,
-                      -1.0,
-                      2.0,
-                      -2.0,
-                      MAX,
-                      MIN,
-                      self.inhale as f64,
-                      fluids[0][0],
-                      fluids[0][1],
-                      fluids[0][2],
-                      fluids[0][3],
-                      fluids[0][4],
-                      fluids[0][5],
-                      fluids[0][6],
-                      fluids[0][7],
-                      fluids[(0 + self.turn) % 6 + 1][0],
-                      fluids[(0 + self.turn) % 6 + 1][1],
-                      fluids[(0 + self.turn) % 6 + 1][2],
-                      fluids[(0 + self.turn) % 6 + 1][3],
-                      fluids[(0 + self.turn) % 6 + 1][4],
-                      fluids[(0 + self.turn) % 6 + 1][5],
-                      fluids[(0 + self.turn) % 6 + 1][6],
-                      fluids[(0 + self.turn) % 6 + 1][7],
-                      fluids[(1 + self.turn) % 6 + 1][0],
-                      fluids[(1 + self.turn) % 6 + 1][1],
-                      fluids[(1 + self.turn) % 6 + 1][2],
-                      fluids[(1 + self.turn) % 6 + 1][3],
-                      fluids[(1 + self.turn) % 6 + 1][4],
-                      fluids[(1 + self.turn) % 6 + 1][5],
-                      fluids[(1 + self.turn) % 6 + 1][6],
-                      fluids[(1 + self.turn) % 6 + 1][7],
-                      fluids[(2 + self.turn) % 6 + 1][0],
-                      fluids[(2 + self.turn) % 6 + 1][1],
-                      fluids[(2 + self.turn) % 6 + 1][2],
-                      fluids[(2 + self.turn) % 6 + 1][3],
-                      fluids[(2 + self.turn) % 6 + 1][4],
-                      fluids[(2 + self.turn) % 6 + 1][5],
-                      fluids[(2 + self.turn) % 6 + 1][6],
-                      fluids[(2 + self.turn) % 6 + 1][7],
-                      fluids[(3 + self.turn) % 6 + 1][0],
-                      fluids[(3 + self.turn) % 6 + 1][1],
-                      fluids[(3 + self.turn) % 6 + 1][2],
-                      fluids[(3 + self.turn) % 6 + 1][3],
-                      fluids[(3 + self.turn) % 6 + 1][4],
-                      fluids[(3 + self.turn) % 6 + 1][5],
-                      fluids[(3 + self.turn) % 6 + 1][6],
-                      fluids[(3 + self.turn) % 6 + 1][7],
-                      fluids[(4 + self.turn) % 6 + 1][0],
-                      fluids[(4 + self.turn) % 6 + 1][1],
-                      fluids[(4 + self.turn) % 6 + 1][2],
-                      fluids[(4 + self.turn) % 6 + 1][3],
-                      fluids[(4 + self.turn) % 6 + 1][4],
-                      fluids[(4 + self.turn) % 6 + 1][5],
-                      fluids[(4 + self.turn) % 6 + 1][6],
-                      fluids[(4 + self.turn) % 6 + 1][7],
-                      fluids[(5 + self.turn) % 6 + 1][0],
-                      fluids[(5 + self.turn) % 6 + 1][1],
-                      fluids[(5 + self.turn) % 6 + 1][2],
-                      fluids[(5 + self.turn) % 6 + 1][3],
-                      fluids[(5 + self.turn) % 6 + 1][4],
-                      fluids[(5 + self.turn) % 6 + 1][5],
-                      fluids[(5 + self.turn) % 6 + 1][6],
-                      fluids[(5 + self.turn) % 6 + 1][7],
-                      nc(cells[(0 + self.turn) % 6]),
-                      nc(cells[(1 + self.turn) % 6]),
-                      nc(cells[(2 + self.turn) % 6]),
-                      nc(cells[(3 + self.turn) % 6]),
-                      nc(cells[(4 + self.turn) % 6]),
-                      nc(cells[(5 + self.turn) % 6]),
-                      self.brain.memory[0],
-                      self.brain.memory[1],
-                      self.brain.memory[2],
-                      self.brain.memory[3]];
-
-        let mut compute = self.brain.mep.compute(&inputs[..]);
+        // Neighbors are sensed relative to the way the cell is facing.
+        let mut sensors = Vec::with_capacity(brain::STATIC_INPUTS);
+        sensors.push(self.inhale);
+        sensors.extend_from_slice(&fluids[0][..]);
+        for i in 0..6 {
+            sensors.extend_from_slice(&fluids[(i + self.turn) % 6 + 1][..]);
+        }
+        let relative = (0..6).map(|i| neighbors[(i + self.turn) % 6]).collect::<Vec<_>>();
+        for n in &relative {
+            sensors.push(nc(n.is_some()));
+        }
+        let senses = rules.senses;
+        for n in &relative {
+            sensors.push(match *n {
+                Some(n) if senses.energy => n.inhale,
+                _ => 0.0,
+            });
+        }
+        for n in &relative {
+            sensors.push(match *n {
+                Some(n) if senses.kinship => self.brain.similarity(&n.brain),
+                _ => 0.0,
+            });
+        }
+        for n in &relative {
+            sensors.push(match *n {
+                Some(n) if senses.facing => ((n.appearance.turn + 6 - self.turn) % 6) as f64,
+                _ => 0.0,
+            });
+        }
+        for n in &relative {
+            sensors.push(match *n {
+                Some(n) if senses.choice => n.appearance.choice,
+                _ => 0.0,
+            });
+        }
+        for i in 0..6 {
+            sensors.extend_from_slice(&distant[(i + self.turn) % 6].fluids[..]);
+        }
+        for i in 0..6 {
+            sensors.push(distant[(i + self.turn) % 6].cells);
+        }
+        // A neighbor's message to this cell is the one it sent in the opposite direction.
+        for i in 0..6 {
+            let d = (i + self.turn) % 6;
+            match neighbors[d] {
+                Some(n) => sensors.extend_from_slice(&n.appearance.messages[(d + 3) % 6][..]),
+                None => sensors.extend_from_slice(&[0.0; brain::CONTACT_MESSAGE][..]),
+            }
+        }
+
+        sensors.push(self.age as f64);
+        for i in 0..6 {
+            sensors.push(nc(walls[(i + self.turn) % 6]));
+        }
+        sensors.extend_from_slice(&self.stored[..]);
+
+        for c in &rules.ablated {
+            for i in c.sensors() {
+                sensors[i] = 0.0;
+            }
+        }
+        sensors
+    }
+
+    /// Runs the brain on sensors built by `sense`, leaving its raw outputs in `actions`.
+    pub fn decide(&mut self,
+                  sensors: &[f64],
+                  actions: &mut [f64; brain::STATIC_OUTPUTS],
+                  rules: &Rules,
+                  rng: &mut Isaac64Rng)
+                  -> Decision {
+        self.brain.decide(sensors, &mut actions[..]);
+        let mut compute = actions.iter().cloned();
 
         let mut coefficients = [[0.0; TOTAL_FLUIDS]; 6];
         for da in &mut coefficients {
@@ -233,6 +574,66 @@ impl Cell {
 
         let suicide_attempt = compute.next().unwrap();
 
+        for m in &mut self.birth_message {
+            *m = compute.next().unwrap();
+        }
+
+        let mut messages = [[0.0; brain::CONTACT_MESSAGE]; 6];
+        for da in &mut messages {
+            for m in da {
+                *m = compute.next().unwrap();
+            }
+        }
+
+        // Actions the rules turn off never take part in arbitration.
+        let transfer_attempt = if rules.gene_transfer == GeneTransfer::Off {
+            compute.next();
+            ::std::f64::NEG_INFINITY
+        } else {
+            compute.next().unwrap()
+        };
+
+        let mut transfer_directions = [0f64; 6];
+        for f in &mut transfer_directions {
+            *f = compute.next().unwrap();
+        }
+
+        let receptive = compute.next().unwrap() > 0.0;
+
+        let mut pumps = [0f64; TOTAL_PUMPED];
+        for p in &mut pumps {
+            let v = compute.next().unwrap();
+            *p = if v.is_normal() {
+                sig(v)
+            } else {
+                0.0
+            };
+        }
+
+        let build_attempt = if rules.terrain {
+            compute.next().unwrap()
+        } else {
+            compute.next();
+            ::std::f64::NEG_INFINITY
+        };
+
+        let mut build_directions = [0f64; 6];
+        for f in &mut build_directions {
+            *f = compute.next().unwrap();
+        }
+
+        let dig_attempt = if rules.terrain {
+            compute.next().unwrap()
+        } else {
+            compute.next();
+            ::std::f64::NEG_INFINITY
+        };
+
+        let mut dig_directions = [0f64; 6];
+        for f in &mut dig_directions {
+            *f = compute.next().unwrap();
+        }
+
         // Handle turn immediately so they can turn to stimuli.
         if let Some(dir) = turn_directions.iter()
             .cloned()
@@ -246,22 +647,18 @@ impl Cell {
             self.turn = dir;
         }
 
-        self.brain.memory.iter_mut().set_from(compute);
-        Decision {
-            choice: match [move_attempt,
-                           divide_attempt,
-                           mate_attempt,
-                           explode_attempt.abs(),
-                           suicide_attempt]
-                .iter()
-                .cloned()
-                .enumerate()
-                .fold((None, 0.0), |best, n| if n.1 > best.1 {
-                    (Some(n.0), n.1)
-                } else {
-                    best
-                })
-                .0 {
+        let checked = rules.arbitration == ArbitrationPolicy::Threshold;
+        let attempts = [move_attempt,
+                        divide_attempt,
+                        mate_attempt,
+                        explode_attempt.abs(),
+                        suicide_attempt,
+                        transfer_attempt,
+                        build_attempt,
+                        dig_attempt];
+        let chosen = self.arbitrate(&attempts, rules, rng);
+        let decision = Decision {
+            choice: match chosen {
                 Some(0) => {
                     Choice::Move(move_directions[..]
                         .iter()
@@ -366,22 +763,26 @@ impl Cell {
                             .1,
                     }
                 }
+                // Exploding and suicide must clear a threshold under every policy. Threshold
+                // arbitration has already checked them against the cell's own thresholds, while
+                // the other policies keep the fixed default.
                 Some(3) => {
-                    if explode_attempt > 1.0 {
-                        Choice::Explode(true)
-                    } else if explode_attempt < -1.0 {
-                        Choice::Explode(false)
+                    if checked || explode_attempt.abs() > DEFAULT_THRESHOLD {
+                        Choice::Explode(explode_attempt > 0.0)
                     } else {
                         Choice::Nothing
                     }
                 }
                 Some(4) => {
-                    if suicide_attempt > 1.0 {
+                    if checked || suicide_attempt > DEFAULT_THRESHOLD {
                         Choice::Suicide
                     } else {
                         Choice::Nothing
                     }
                 }
+                Some(5) => Choice::Transfer(self.facing_direction(&transfer_directions)),
+                Some(6) => Choice::Build(self.facing_direction(&build_directions)),
+                Some(7) => Choice::Dig(self.facing_direction(&dig_directions)),
                 _ => Choice::Nothing,
             },
             coefficients: {
@@ -416,28 +817,208 @@ impl Cell {
                 }
                 ncoef
             },
+            effort: chosen.map_or(0.0, |i| sig(attempts[i].abs())),
+            receptive: receptive,
+            pumps: pumps,
+        };
+        let mut sent = [[0.0; brain::CONTACT_MESSAGE]; 6];
+        for (i, m) in messages.iter().enumerate() {
+            sent[(i + self.turn) % 6] = *m;
         }
+        self.next_appearance = Appearance {
+            turn: self.turn,
+            choice: decision.choice.code(),
+            messages: sent,
+        };
+        decision
     }
 
-    pub fn mate(&mut self, other: &Cell, rng: &mut Isaac64Rng) -> Cell {
-        self.inhale /= 2;
+    /// Both parents give a share of their inhale to the offspring.
+    pub fn mate(&mut self, other: &mut Cell, rules: &Rules, rng: &mut Isaac64Rng) -> Cell {
+        let inhale = self.give(rules) + other.give(rules);
+        let genes = self.genes.mate(&other.genes, rng);
+        let mut brain = self.brain.mate(&other.brain, &genes, rules, rng);
+        self.inherit(&mut brain, Some(&*other), rules);
         Cell {
-            inhale: self.inhale,
+            inhale: inhale,
             suicide: false,
-            brain: self.brain.mate(&other.brain, rng),
+            birth_message: [0.0; brain::BIRTH_MESSAGE],
+            stored: [0.0; TOTAL_PUMPED],
+            brain: brain,
+            genes: genes,
+            id: rng.gen(),
+            age: 0,
             turn: self.turn,
+            appearance: Appearance::new(self.turn),
+            next_appearance: Appearance::new(self.turn),
         }
     }
 
-    pub fn divide(&mut self, rng: &mut Isaac64Rng) -> Cell {
-        self.inhale /= 2;
+    pub fn divide(&mut self, rules: &Rules, rng: &mut Isaac64Rng) -> Cell {
+        let inhale = self.give(rules);
+        let genes = self.genes.divide(rng);
+        let mut brain = self.brain.divide(&genes, rules, rng);
+        self.inherit(&mut brain, None, rules);
         Cell {
-            inhale: self.inhale,
+            inhale: inhale,
             suicide: false,
-            brain: self.brain.divide(rng),
+            birth_message: [0.0; brain::BIRTH_MESSAGE],
+            stored: [0.0; TOTAL_PUMPED],
+            brain: brain,
+            genes: genes,
+            id: rng.gen(),
+            age: 0,
             turn: self.turn,
+            appearance: Appearance::new(self.turn),
+            next_appearance: Appearance::new(self.turn),
+        }
+    }
+
+    /// Secretes or absorbs each pumped fluid at the rates in `pumps`, paying `costs` for every unit
+    /// moved and storing no more than `caps`. Returns the inhale spent.
+    pub fn pump(&mut self,
+                fluids: &mut [f64; TOTAL_FLUIDS],
+                pumps: &[f64; TOTAL_PUMPED],
+                costs: &[f64; TOTAL_PUMPED],
+                caps: &[f64; TOTAL_PUMPED])
+                -> f64 {
+        let mut spent = 0.0;
+        for (i, &fluid) in PUMPED_FLUIDS.iter().enumerate() {
+            let secrete = pumps[i] > 0.0;
+            let mut amount = if secrete {
+                pumps[i] * self.stored[i]
+            } else {
+                (-pumps[i] * fluids[fluid]).min(caps[i] - self.stored[i]).max(0.0)
+            };
+            // Only pump as much as the cell can pay for.
+            if costs[i] > 0.0 {
+                amount = amount.min(self.inhale / costs[i]);
+            }
+            let cost = costs[i] * amount;
+            self.inhale -= cost;
+            spent += cost;
+            if secrete {
+                self.stored[i] -= amount;
+                fluids[fluid] += amount;
+            } else {
+                self.stored[i] += amount;
+                fluids[fluid] -= amount;
+            }
+        }
+        spent
+    }
+
+    /// Whether this cell is able and willing to mate with another.
+    pub fn accepts(&self, other: &Cell, rules: &Rules) -> bool {
+        let n = rules.mating_types;
+        let compatible = n < 2 || self.genes.mating_type % n != other.genes.mating_type % n;
+        let willing = !rules.mate_choice ||
+                      (other.genes.ornament - self.genes.preference).abs() <= self.genes.tolerance;
+        compatible && willing
+    }
+
+    /// Takes the share of inhale this cell gives to an offspring.
+    fn give(&mut self, rules: &Rules) -> f64 {
+        let share = match rules.energy_split {
+            EnergySplit::Fixed(share) => share,
+            EnergySplit::Heritable => self.genes.offspring_share,
+        };
+        let given = self.inhale * share;
+        self.inhale -= given;
+        given
+    }
+
+    /// The absolute direction of the largest value, where the values are relative to the way the
+    /// cell is facing.
+    fn facing_direction(&self, values: &[f64; 6]) -> Direction {
+        let best = (0..6).fold(0, |best, i| if values[i] > values[best] {
+            i
+        } else {
+            best
+        });
+        DIRECTIONS[(best + self.turn) % 6]
+    }
+
+    /// Picks which of the attempts to act on, if any.
+    fn arbitrate(&self, attempts: &[f64], rules: &Rules, rng: &mut Isaac64Rng) -> Option<usize> {
+        match rules.arbitration {
+            ArbitrationPolicy::Argmax => strongest(attempts, 0.0),
+            ArbitrationPolicy::Threshold => {
+                let margins = attempts.iter()
+                    .zip(&self.genes.thresholds)
+                    .map(|(&a, &t)| a - t)
+                    .collect::<Vec<_>>();
+                strongest(&margins, 0.0)
+            }
+            ArbitrationPolicy::Softmax => softmax(attempts, self.genes.temperature, rng),
+        }
+    }
+
+    /// Fills the memory of an offspring's brain according to the memory inheritance mode.
+    fn inherit(&self, child: &mut Mind, other: Option<&Cell>, rules: &Rules) {
+        match rules.memory_inheritance {
+            MemoryInheritance::Reset => {}
+            MemoryInheritance::Copy => {
+                for (c, &p) in child.memory_mut().iter_mut().zip(self.brain.memory()) {
+                    *c = p;
+                }
+            }
+            MemoryInheritance::Average => {
+                let other = other.unwrap_or(self);
+                for ((c, &a), &b) in child.memory_mut()
+                    .iter_mut()
+                    .zip(self.brain.memory())
+                    .zip(other.brain.memory()) {
+                    *c = 0.5 * (a + b);
+                }
+            }
+            MemoryInheritance::Message => {
+                for (c, &m) in child.memory_mut().iter_mut().zip(&self.birth_message) {
+                    *c = m;
+                }
+            }
+        }
+    }
+}
+
+/// The index of the largest value if it exceeds the floor.
+fn strongest(values: &[f64], floor: f64) -> Option<usize> {
+    values.iter()
+        .cloned()
+        .enumerate()
+        .fold((None, floor), |best, n| if n.1 > best.1 {
+            (Some(n.0), n.1)
+        } else {
+            best
+        })
+        .0
+}
+
+/// Samples an index with a probability proportional to `exp(value / temperature)`, or none with
+/// the probability a value of 0 would have.
+fn softmax(values: &[f64], temperature: f64, rng: &mut Isaac64Rng) -> Option<usize> {
+    let finite = |v: f64| if v.is_nan() {
+        ::std::f64::NEG_INFINITY
+    } else {
+        v
+    };
+    let max = values.iter().cloned().map(&finite).fold(0.0, f64::max);
+    if max == ::std::f64::INFINITY {
+        return values.iter().position(|&v| v == max);
+    }
+    // Subtracting the largest value keeps the exponentials from overflowing.
+    let weights = values.iter()
+        .map(|&v| ((finite(v) - max) / temperature).exp())
+        .collect::<Vec<_>>();
+    let total = weights.iter().fold((-max / temperature).exp(), |acc, &w| acc + w);
+    let mut r = rng.gen_range(0.0, total);
+    for (i, &w) in weights.iter().enumerate() {
+        if r < w {
+            return Some(i);
         }
+        r -= w;
     }
+    None
 }
 
 /// In the range (-1.0, 1.0).
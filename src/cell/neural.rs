@@ -0,0 +1,166 @@
+use rand::{Rng, Isaac64Rng};
+use itertools::Itertools;
+use super::Rules;
+use super::brain::{STATIC_INPUTS, STATIC_OUTPUTS};
+use super::controller::Controller;
+use super::genes::Genes;
+
+pub const HIDDEN_UNITS: usize = 16;
+// Each hidden unit sees the sensors, the previous hidden state and a bias.
+const HIDDEN_FAN_IN: usize = STATIC_INPUTS + HIDDEN_UNITS + 1;
+// Each output sees the hidden state and a bias.
+const OUTPUT_FAN_IN: usize = HIDDEN_UNITS + 1;
+const MUTATE_STEP: f64 = 0.25;
+
+/// A recurrent neural network with a single hidden layer that feeds back into itself.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Network {
+    hidden_weights: Vec<f64>,
+    output_weights: Vec<f64>,
+    pub hidden: Vec<f64>,
+}
+
+impl Network {
+    pub fn new(rng: &mut Isaac64Rng) -> Self {
+        let hidden_range = 1.0 / (HIDDEN_FAN_IN as f64).sqrt();
+        let output_range = 1.0 / (OUTPUT_FAN_IN as f64).sqrt();
+        Network {
+            hidden_weights: (0..HIDDEN_UNITS * HIDDEN_FAN_IN)
+                .map(|_| rng.gen_range(-hidden_range, hidden_range))
+                .collect_vec(),
+            output_weights: (0..STATIC_OUTPUTS * OUTPUT_FAN_IN)
+                .map(|_| rng.gen_range(-output_range, output_range))
+                .collect_vec(),
+            hidden: vec![0.0; HIDDEN_UNITS],
+        }
+    }
+
+    fn weight_mut(&mut self, i: usize) -> &mut f64 {
+        if i < self.hidden_weights.len() {
+            &mut self.hidden_weights[i]
+        } else {
+            &mut self.output_weights[i - self.hidden_weights.len()]
+        }
+    }
+}
+
+/// Crosses two sets of weights, switching parents at each crossover point.
+fn crossover(a: &[f64], b: &[f64], points: usize, rng: &mut Isaac64Rng) -> Vec<f64> {
+    let mut cuts = (0..points).map(|_| rng.gen_range(0, a.len())).collect_vec();
+    cuts.sort();
+    let mut from_a = true;
+    let mut next = 0;
+    (0..a.len())
+        .map(|i| {
+            while next < cuts.len() && cuts[next] == i {
+                from_a = !from_a;
+                next += 1;
+            }
+            if from_a {
+                a[i]
+            } else {
+                b[i]
+            }
+        })
+        .collect_vec()
+}
+
+impl Controller for Network {
+    fn inputs(&self) -> usize {
+        STATIC_INPUTS
+    }
+
+    fn outputs(&self) -> usize {
+        STATIC_OUTPUTS
+    }
+
+    fn decide(&mut self, sensors: &[f64], actions: &mut [f64]) {
+        let hidden = (0..HIDDEN_UNITS)
+            .map(|h| {
+                let w = &self.hidden_weights[h * HIDDEN_FAN_IN..(h + 1) * HIDDEN_FAN_IN];
+                let sum = sensors.iter()
+                    .chain(&self.hidden)
+                    .zip(w)
+                    .fold(w[HIDDEN_FAN_IN - 1], |acc, (&v, &w)| acc + v * w);
+                sum.tanh()
+            })
+            .collect_vec();
+        for (o, a) in actions.iter_mut().enumerate() {
+            let w = &self.output_weights[o * OUTPUT_FAN_IN..(o + 1) * OUTPUT_FAN_IN];
+            *a = hidden.iter().zip(w).fold(w[OUTPUT_FAN_IN - 1], |acc, (&v, &w)| acc + v * w);
+        }
+        self.hidden = hidden;
+    }
+
+    fn reset(&mut self) {
+        for h in &mut self.hidden {
+            *h = 0.0;
+        }
+    }
+
+    fn prepare(&mut self) {}
+
+    /// Splices a random segment of each layer of the donor's weights into this network.
+    fn transfer(&mut self, donor: &Self, rng: &mut Isaac64Rng) {
+        self.hidden_weights = crossover(&self.hidden_weights, &donor.hidden_weights, 2, rng);
+        self.output_weights = crossover(&self.output_weights, &donor.output_weights, 2, rng);
+    }
+
+    /// Decays towards zero as the average difference between weights grows.
+    fn similarity(&self, other: &Self) -> f64 {
+        let total = self.hidden_weights.len() + self.output_weights.len();
+        let difference = self.hidden_weights
+            .iter()
+            .chain(&self.output_weights)
+            .zip(other.hidden_weights.iter().chain(&other.output_weights))
+            .fold(0.0, |acc, (a, b)| acc + (a - b).abs());
+        (-difference / total as f64).exp()
+    }
+
+    fn memory(&self) -> &[f64] {
+        &self.hidden
+    }
+
+    fn memory_mut(&mut self) -> &mut [f64] {
+        &mut self.hidden
+    }
+
+    // Networks have a fixed size, so complexity costs don't apply to them.
+    fn instructions(&self) -> usize {
+        0
+    }
+
+    fn registers(&self) -> usize {
+        0
+    }
+
+    fn mutate(&mut self, genes: &Genes, _: &Rules, rng: &mut Isaac64Rng) {
+        if genes.roll(rng) {
+            let total = self.hidden_weights.len() + self.output_weights.len();
+            for _ in 0..genes.mutate_size {
+                *self.weight_mut(rng.gen_range(0, total)) +=
+                    rng.gen_range(-MUTATE_STEP, MUTATE_STEP);
+            }
+        }
+    }
+
+    fn mate(&self, other: &Self, genes: &Genes, rules: &Rules, rng: &mut Isaac64Rng) -> Self {
+        let points = genes.crossover_points;
+        let mut n = Network {
+            hidden_weights: crossover(&self.hidden_weights, &other.hidden_weights, points, rng),
+            output_weights: crossover(&self.output_weights, &other.output_weights, points, rng),
+            hidden: vec![0.0; HIDDEN_UNITS],
+        };
+        // Perform unit mutations on offspring
+        n.mutate(genes, rules, rng);
+        n
+    }
+
+    fn divide(&self, genes: &Genes, rules: &Rules, rng: &mut Isaac64Rng) -> Self {
+        let mut n = self.clone();
+        n.reset();
+        // Perform unit mutations on offspring
+        n.mutate(genes, rules, rng);
+        n
+    }
+}
@@ -0,0 +1,75 @@
+//! Measures how much each output of a brain depends on each of its inputs by probing it with
+//! inputs sampled from a running grid.
+//!
+//! Effects are measured on the raw outputs of the brain, before `Cell::decide` arbitrates them
+//! into a choice and squashes them into coefficients. A large effect on an attempt output
+//! therefore doesn't mean the choice changes, and effects on outputs that `decide` clamps or
+//! ignores are reported all the same.
+
+use super::brain::{Brain, CONST_INPUTS, TOTAL_INPUTS, TOTAL_OUTPUTS};
+use super::disasm::{input_label, output_label};
+
+/// How an input is changed to probe the brain.
+#[derive(Clone, Copy, Debug)]
+pub enum Probe {
+    Zero,
+    Nudge(f64),
+}
+
+#[derive(Clone, Debug)]
+pub struct Sensitivity {
+    /// The mean absolute change of each output, indexed by input and then output.
+    pub effects: Vec<Vec<f64>>,
+}
+
+impl Sensitivity {
+    /// Probes every input of a brain with each sample of sensors.
+    ///
+    /// Memory inputs take the brain's current memory. Constant inputs are folded into the brain
+    /// when it is compiled, so they are never probed. Changes that aren't finite are ignored.
+    pub fn new(brain: &Brain, samples: &[Vec<f64>], probe: Probe) -> Self {
+        let mut brain = brain.clone();
+        let mut effects = vec![vec![0.0; TOTAL_OUTPUTS]; TOTAL_INPUTS];
+        for sensors in samples {
+            let inputs = brain.full_inputs(sensors);
+            let base = brain.compute(&inputs[..]);
+            for i in CONST_INPUTS..TOTAL_INPUTS {
+                let mut probed = inputs.clone();
+                probed[i] = match probe {
+                    Probe::Zero => 0.0,
+                    Probe::Nudge(amount) => probed[i] + amount,
+                };
+                let outputs = brain.compute(&probed[..]);
+                for (e, (&a, &b)) in effects[i].iter_mut().zip(base.iter().zip(outputs.iter())) {
+                    let change = (a - b).abs();
+                    if change.is_finite() {
+                        *e += change;
+                    }
+                }
+            }
+        }
+        if !samples.is_empty() {
+            for e in effects.iter_mut().flat_map(|e| e.iter_mut()) {
+                *e /= samples.len() as f64;
+            }
+        }
+        Sensitivity { effects: effects }
+    }
+
+    /// The strongest effects as input, output and mean change, strongest first.
+    pub fn strongest(&self, count: usize) -> Vec<(String, String, f64)> {
+        let mut all = Vec::new();
+        for (i, e) in self.effects.iter().enumerate() {
+            for (o, &change) in e.iter().enumerate() {
+                if change > 0.0 {
+                    all.push((i, o, change));
+                }
+            }
+        }
+        all.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
+        all.into_iter()
+            .take(count)
+            .map(|(i, o, change)| (input_label(i), output_label(o), change))
+            .collect()
+    }
+}
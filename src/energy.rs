@@ -0,0 +1,163 @@
+//! Accounting for the energy that flows between food and cells.
+//!
+//! Energy is measured in units of inhale. Food holds one unit of energy for every `consumption`
+//! of food fluid.
+
+use std::fmt;
+use super::fluid::{FLUID_NAMES, TOTAL_FLUIDS};
+
+/// Energy that flowed into, out of and between cells during a cycle.
+#[derive(Clone, Debug, Default)]
+pub struct Budget {
+    /// Given to cells that were spawned or placed.
+    pub spawned: f64,
+    /// Taken from food by cells.
+    pub eaten: f64,
+    /// Spent by cells on living and acting, which leaves the grid.
+    pub spent: f64,
+    /// Held by cells when they died.
+    pub died: f64,
+    /// Returned to food by cells that died.
+    pub released: f64,
+}
+
+impl Budget {
+    pub fn add(&mut self, other: &Budget) {
+        self.spawned += other.spawned;
+        self.eaten += other.eaten;
+        self.spent += other.spent;
+        self.died += other.died;
+        self.released += other.released;
+    }
+
+    /// The flows since an earlier snapshot of this budget.
+    pub fn since(&self, earlier: &Budget) -> Budget {
+        Budget {
+            spawned: self.spawned - earlier.spawned,
+            eaten: self.eaten - earlier.eaten,
+            spent: self.spent - earlier.spent,
+            died: self.died - earlier.died,
+            released: self.released - earlier.released,
+        }
+    }
+
+    /// How much the energy held by cells should have changed.
+    pub fn cell_change(&self) -> f64 {
+        self.spawned + self.eaten - self.spent - self.died
+    }
+
+    /// How much the energy held as food should have changed, leaving out fluid reactions.
+    pub fn food_change(&self) -> f64 {
+        self.released - self.eaten
+    }
+}
+
+impl fmt::Display for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "spawned {:.3}, eaten {:.3}, spent {:.3}, died {:.3}, released {:.3} (cells {:+.3}, \
+                food {:+.3})",
+               self.spawned,
+               self.eaten,
+               self.spent,
+               self.died,
+               self.released,
+               self.cell_change(),
+               self.food_change())
+    }
+}
+
+/// The phases of a grid cycle, in the order they run.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Spawn,
+    Cells,
+    Decisions,
+    Fluids,
+    Death,
+}
+
+/// The total amount of every fluid and of the energy held by cells at one point in a cycle.
+#[derive(Clone, Debug)]
+pub struct Totals {
+    pub fluids: [f64; TOTAL_FLUIDS],
+    pub cells: f64,
+}
+
+impl Totals {
+    /// The energy held by cells and food, including food that cells store.
+    pub fn energy(&self, consumption: f64) -> f64 {
+        self.cells + self.fluids[0] / consumption
+    }
+}
+
+/// A quantity that the audit checks against the budget.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Quantity {
+    /// The energy held by cells.
+    Cells,
+    /// The energy held by cells and food together, which only spawning, spending and reactions
+    /// should change.
+    Total,
+    /// The energy that cells held when they died but didn't return to food.
+    DeathLoss,
+    Fluid(usize),
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Quantity::Cells => write!(f, "cell energy"),
+            Quantity::Total => write!(f, "total energy"),
+            Quantity::DeathLoss => write!(f, "death loss"),
+            Quantity::Fluid(i) => write!(f, "{}", FLUID_NAMES[i]),
+        }
+    }
+}
+
+/// A quantity that changed by more than the budget accounts for during a phase.
+#[derive(Clone, Debug)]
+pub struct Drift {
+    pub phase: Phase,
+    pub quantity: Quantity,
+    pub expected: f64,
+    pub actual: f64,
+}
+
+impl Drift {
+    pub fn amount(&self) -> f64 {
+        self.actual - self.expected
+    }
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{:?}: {} changed by {:+.6}, expected {:+.6} (drift {:+.6})",
+               self.phase,
+               self.quantity,
+               self.actual,
+               self.expected,
+               self.amount())
+    }
+}
+
+/// Summarizes the drifts of a cycle on one line, or gives `None` if nothing drifted.
+pub fn summary(drifts: &[Drift]) -> Option<String> {
+    let mut worst: Option<&Drift> = None;
+    let mut total = 0.0;
+    for d in drifts {
+        if worst.map_or(true, |w| d.amount().abs() > w.amount().abs()) {
+            worst = Some(d);
+        }
+        if d.quantity == Quantity::Total {
+            total += d.amount();
+        }
+    }
+    worst.map(|w| {
+        format!("{} drifts, total energy drift {:+.6}, worst {}",
+                drifts.len(),
+                total,
+                w)
+    })
+}
@@ -12,6 +12,17 @@ pub const NORMAL_DIFFUSION: [f64; TOTAL_FLUIDS] = [0.0004 * ACCURACY,
                                                    SIGNAL_FLUID_DIFFUSION * ACCURACY,
                                                    SIGNAL_FLUID_DIFFUSION * ACCURACY,
                                                    SIGNAL_FLUID_DIFFUSION * ACCURACY];
+pub const TOTAL_PUMPED: usize = 3;
+/// The fluids cells can secrete into and absorb from their hex: food, B and kill fluid.
+pub const PUMPED_FLUIDS: [usize; TOTAL_PUMPED] = [0, 2, 3];
+pub const FLUID_NAMES: [&'static str; TOTAL_FLUIDS] = ["food",
+                                                       "A",
+                                                       "B",
+                                                       "kill",
+                                                       "signal0",
+                                                       "signal1",
+                                                       "signal2",
+                                                       "signal3"];
 pub const KILL_FLUID_NORMAL: f64 = 0.05;
 pub const KILL_FLUID_DECAY: f64 = 0.15 * ACCURACY;
 pub const KILL_FLUID_UPPER_THRESHOLD: f64 = 0.052;
@@ -61,6 +72,17 @@ impl Solution {
          -SIGNAL_FLUID_DECAY * self.fluids[7]]
     }
 
+    /// How much `end_cycle` will add to each normal fluid from reactions and from sources other
+    /// than diffusion between neighbors, such as explosions.
+    pub fn sources(&self) -> [f64; 4] {
+        let reacts = self.react_deltas();
+        let mut sources = [0.0; 4];
+        for i in 0..4 {
+            sources[i] = TIMESTEP * (reacts[i] + self.diffuse[i]);
+        }
+        sources
+    }
+
     pub fn diffuse_from(&mut self, other: &Solution, dtype: DiffusionType, direction: usize) {
         // Handle normal fluids.
         for i in 0..4 {
@@ -91,6 +113,11 @@ impl Solution {
         acc / 6.0
     }
 
+    /// Ends a cycle without reacting or decaying, discarding anything diffused into the solution.
+    pub fn hold(&mut self) {
+        self.diffuse = [0.0; TOTAL_FLUIDS];
+    }
+
     pub fn end_cycle(&mut self) {
         let reacts = self.react_deltas();
         // Handle normal fluids.
@@ -1,8 +1,15 @@
 use super::cell::*;
 use super::fluid::*;
+use super::library::Library;
+use super::stats::{Distribution, Stats};
+use super::trace::{self, Record, Trace};
+use super::energy::{Budget, Drift, Phase, Quantity, Totals};
+use super::cell::controller::Controller;
+use super::cell::genes::Genes;
 use itertools::Itertools;
+use std::io;
 use std::mem;
-use rand::{Isaac64Rng, Rng};
+use rand::{Isaac64Rng, Rng, SeedableRng};
 use noise::{Brownian2, perlin2};
 use num_cpus;
 use crossbeam;
@@ -11,6 +18,36 @@ const KILL_FLUID_COLOR_NORMAL: f64 = 0.01;
 const SIGNAL_FLUID_SQRT_NORMAL: f64 = 5.0;
 const SIGNAL_FLUID_COLOR_NORMAL: f32 = 0.4;
 const FOOD_FLUID_COLOR_NORMAL: f64 = 600.0;
+const WALL_COLOR: [f32; 4] = [0.4, 0.4, 0.4, 1.0];
+
+const ARCHIVE_SIZE: usize = 256;
+// Chance that a newborn cell has its brain and genes recorded in the archive.
+const ARCHIVE_PROBABILITY: f64 = 0.01;
+// How many of the most recent deaths lifespan statistics cover.
+const LIFESPAN_HISTORY: usize = 4096;
+
+/// Where the brains of spawned cells come from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SpawnSource {
+    Random,
+    Library(Library),
+    Archive,
+}
+
+/// A rectangle of hexes that wraps around the edges of the grid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    fn area(&self) -> usize {
+        self.width * self.height
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Mate {
@@ -29,6 +66,9 @@ pub struct Hex {
     pub solution: Solution,
     pub cell: Option<Cell>,
     pub decision: Option<Decision>,
+    /// Walls hold no cells and block movement and diffusion. Their fluids stay as they were when
+    /// the wall was built, without reacting.
+    pub wall: bool,
     delta: Delta,
 }
 
@@ -39,6 +79,9 @@ unsafe impl Send for GridCont {}
 
 impl Hex {
     pub fn color(&self) -> [f32; 4] {
+        if self.wall {
+            return WALL_COLOR;
+        }
         let killf = ((self.solution.fluids[3] - KILL_FLUID_NORMAL) /
                      KILL_FLUID_COLOR_NORMAL) as f32;
         let mut ocolors = [killf.abs(),
@@ -61,18 +104,63 @@ impl Hex {
 #[derive(Serialize, Deserialize)]
 pub struct Grid {
     pub spawning: bool,
+    pub spawn_source: SpawnSource,
+    /// If this is empty, cells spawn anywhere in the grid.
+    pub spawn_regions: Vec<Region>,
     pub width: usize,
     pub height: usize,
     pub consumption: f64,
     pub spawn_rate: f64,
-    pub inhale_minimum: usize,
-    pub inhale_cap: usize,
-    pub movement_cost: usize,
-    pub divide_cost: usize,
-    pub explode_requirement: usize,
+    pub inhale_minimum: f64,
+    pub inhale_cap: f64,
+    /// The fraction of the food in its hex a cell eats each cycle.
+    pub intake_rate: f64,
+    /// Inhale every cell spends each cycle just to stay alive.
+    pub metabolism: f64,
+    /// Inhale a cell spends on a cycle in which it acts at full effort.
+    pub activity_cost: f64,
+    pub movement_cost: f64,
+    pub divide_cost: f64,
+    /// Charged to both parents when mating.
+    pub mate_cost: f64,
+    /// Charged to a cell that transfers genes to a neighbor.
+    pub transfer_cost: f64,
+    /// Charged to a cell that builds or digs a wall.
+    pub wall_cost: f64,
+    pub explode_requirement: f64,
     pub death_release_coefficient: f64,
     pub explode_amount: f64,
+    /// Inhale charged each cycle for every instruction in a cell's genome.
+    pub instruction_cost: f64,
+    /// Inhale charged each cycle for every memory register a cell uses.
+    pub register_cost: f64,
+    /// Inhale charged each cycle for every unit of kill fluid in the range a cell survives, when
+    /// the range is heritable.
+    pub kill_tolerance_cost: f64,
+    /// Inhale charged for every unit of each pumped fluid a cell secretes or absorbs.
+    pub pump_costs: [f64; TOTAL_PUMPED],
+    /// The most of each pumped fluid a cell can store.
+    pub storage_caps: [f64; TOTAL_PUMPED],
+    /// When set, every phase of the cycle is checked for energy or fluid that appeared or
+    /// vanished by more than this tolerance.
+    pub audit: Option<f64>,
+    pub rules: Rules,
     tiles: Vec<Hex>,
+    archive: Vec<(controller::Mind, Genes)>,
+    archive_next: usize,
+    #[serde(skip_serializing, skip_deserializing)]
+    trace: Option<Trace>,
+    /// The ages at which recent cells died.
+    #[serde(skip_serializing, skip_deserializing)]
+    lifespans: Vec<u64>,
+    #[serde(skip_serializing, skip_deserializing)]
+    lifespans_next: usize,
+    /// The energy flows since the budget was last taken, including cells placed between cycles.
+    #[serde(skip_serializing, skip_deserializing)]
+    budget: Budget,
+    /// What the audit found in the last cycle.
+    #[serde(skip_serializing, skip_deserializing)]
+    drifts: Vec<Drift>,
 }
 
 impl Grid {
@@ -80,29 +168,62 @@ impl Grid {
                height: usize,
                consumption: f64,
                spawn_rate: f64,
-               inhale_minimum: usize,
-               inhale_cap: usize,
-               movement_cost: usize,
-               divide_cost: usize,
-               explode_requirement: usize,
+               inhale_minimum: f64,
+               inhale_cap: f64,
+               intake_rate: f64,
+               metabolism: f64,
+               activity_cost: f64,
+               movement_cost: f64,
+               divide_cost: f64,
+               mate_cost: f64,
+               transfer_cost: f64,
+               wall_cost: f64,
+               explode_requirement: f64,
                death_release_coefficient: f64,
                explode_amount: f64,
+               instruction_cost: f64,
+               register_cost: f64,
+               kill_tolerance_cost: f64,
+               pump_costs: [f64; TOTAL_PUMPED],
+               storage_caps: [f64; TOTAL_PUMPED],
                rng: &mut Isaac64Rng)
                -> Self {
         Grid {
             spawning: true,
+            spawn_source: SpawnSource::Random,
+            spawn_regions: Vec::new(),
             width: width,
             height: height,
             consumption: consumption,
             spawn_rate: spawn_rate,
             inhale_minimum: inhale_minimum,
             inhale_cap: inhale_cap,
+            intake_rate: intake_rate,
+            metabolism: metabolism,
+            activity_cost: activity_cost,
             movement_cost: movement_cost,
             divide_cost: divide_cost,
+            mate_cost: mate_cost,
+            transfer_cost: transfer_cost,
+            wall_cost: wall_cost,
             explode_requirement: explode_requirement,
             death_release_coefficient: death_release_coefficient,
             explode_amount: explode_amount,
+            instruction_cost: instruction_cost,
+            register_cost: register_cost,
+            kill_tolerance_cost: kill_tolerance_cost,
+            pump_costs: pump_costs,
+            storage_caps: storage_caps,
+            audit: None,
+            rules: Rules::new(),
             tiles: randomizing_vec(width, height, rng),
+            archive: Vec::new(),
+            archive_next: 0,
+            trace: None,
+            lifespans: Vec::new(),
+            lifespans_next: 0,
+            budget: Budget::default(),
+            drifts: Vec::new(),
         }
     }
 
@@ -118,9 +239,8 @@ impl Grid {
         &mut self.tiles[x + y * self.width]
     }
 
-    fn hex_and_neighbors(&mut self, x: usize, y: usize) -> (&mut Hex, [&Hex; 6]) {
-        (unsafe { mem::transmute(self.hex_mut(x, y)) },
-         if y % 2 == 0 {
+    fn neighbors(&self, x: usize, y: usize) -> [&Hex; 6] {
+        if y % 2 == 0 {
             [// UpRight
              self.hex((x + self.width + 1) % self.width,
                       (y + self.height - 1) % self.height),
@@ -150,76 +270,463 @@ impl Grid {
              self.hex(x, (y + self.height + 1) % self.height),
              // Right
              self.hex((x + self.width + 1) % self.width, y)]
+        }
+    }
+
+    fn hex_and_neighbors(&mut self, x: usize, y: usize) -> (&mut Hex, [&Hex; 6]) {
+        (unsafe { mem::transmute(self.hex_mut(x, y)) }, self.neighbors(x, y))
+    }
+
+    /// The hexes at exactly `radius` steps from a hex, along with the sector each is in.
+    pub fn ring(&self, x: usize, y: usize, radius: usize) -> Ring {
+        Ring::new(x, y, self.width, self.height, radius)
+    }
+
+    /// Aggregates the hexes at a distance of 2 up to `radius` from a hex in each sector.
+    fn distant(&self, x: usize, y: usize, radius: usize) -> [Distant; 6] {
+        let mut distant = [Distant::new(); 6];
+        let mut counts = [0usize; 6];
+        for r in 2..radius + 1 {
+            for (sector, (hx, hy)) in self.ring(x, y, r) {
+                let hex = self.hex(hx, hy);
+                for (d, &f) in distant[sector].fluids.iter_mut().zip(&hex.solution.fluids) {
+                    *d += f;
+                }
+                if hex.cell.is_some() {
+                    distant[sector].cells += 1.0;
+                }
+                counts[sector] += 1;
+            }
+        }
+        for (d, &n) in distant.iter_mut().zip(&counts) {
+            if n != 0 {
+                for f in &mut d.fluids {
+                    *f /= n as f64;
+                }
+            }
+        }
+        distant
+    }
+
+    /// The sensors of the cell at a hex, as it would see the grid right now.
+    fn sensors_at(&self, x: usize, y: usize) -> Option<Vec<f64>> {
+        let this = self.hex(x, y);
+        let neighbors = self.neighbors(x, y);
+        this.cell.as_ref().map(|c| {
+            c.sense([&this.solution.fluids,
+                     &neighbors[0].solution.fluids,
+                     &neighbors[1].solution.fluids,
+                     &neighbors[2].solution.fluids,
+                     &neighbors[3].solution.fluids,
+                     &neighbors[4].solution.fluids,
+                     &neighbors[5].solution.fluids],
+                    [neighbors[0].cell.as_ref(),
+                     neighbors[1].cell.as_ref(),
+                     neighbors[2].cell.as_ref(),
+                     neighbors[3].cell.as_ref(),
+                     neighbors[4].cell.as_ref(),
+                     neighbors[5].cell.as_ref()],
+                    &self.distant(x, y, self.rules.sense_radius),
+                    [neighbors[0].wall,
+                     neighbors[1].wall,
+                     neighbors[2].wall,
+                     neighbors[3].wall,
+                     neighbors[4].wall,
+                     neighbors[5].wall],
+                    &self.rules)
         })
     }
 
+    /// Samples the sensors of randomly chosen cells, for probing brains with realistic inputs.
+    pub fn sample_sensors(&self, count: usize, rng: &mut Isaac64Rng) -> Vec<Vec<f64>> {
+        let occupied = (0..self.tiles.len())
+            .filter(|&i| self.tiles[i].cell.is_some())
+            .collect_vec();
+        if occupied.is_empty() {
+            return Vec::new();
+        }
+        (0..count)
+            .filter_map(|_| {
+                let i = occupied[rng.gen_range(0, occupied.len())];
+                self.sensors_at(i % self.width, i / self.width)
+            })
+            .collect_vec()
+    }
+
+    /// Starts recording every decision of the cell with an ID to a file until it dies.
+    pub fn tag(&mut self, id: u64, path: &str) -> io::Result<()> {
+        let trace = match self.tiles.iter().filter_map(|h| h.cell.as_ref()).find(|c| c.id == id) {
+            Some(c) => try!(Trace::create(id, &c.brain, path)),
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "No cell has that ID")),
+        };
+        self.trace = Some(trace);
+        Ok(())
+    }
+
+    pub fn untag(&mut self) {
+        self.trace = None;
+    }
+
+    /// The ID of the cell being traced, if it is still alive.
+    pub fn tagged(&self) -> Option<u64> {
+        self.trace.as_ref().map(|t| t.id)
+    }
+
     pub fn cycle(&mut self, rng: &mut Isaac64Rng) {
+        self.drifts.clear();
+
         if self.spawning {
-            self.cycle_spawn(rng);
+            self.audited(Phase::Spawn, |g| g.cycle_spawn(rng));
         }
 
-        self.cycle_cells();
+        self.audited(Phase::Cells, |g| g.cycle_cells(rng));
 
-        self.cycle_decisions(rng);
+        self.audited(Phase::Decisions, |g| g.cycle_decisions(rng));
 
-        self.cycle_fluids();
+        self.audited(Phase::Fluids, |g| g.cycle_fluids());
 
-        self.cycle_death();
+        self.audited(Phase::Death, |g| g.cycle_death(rng));
     }
 
-    fn cycle_spawn(&mut self, rng: &mut Isaac64Rng) {
-        if self.spawn_rate >= 1.0 {
-            for _ in 0..self.spawn_rate as usize {
-                let tile = rng.gen_range(0, self.width * self.height);
-                if self.tiles[tile].cell.is_none() {
-                    self.tiles[tile].cell = Some(Cell::new(rng));
+    /// Runs a phase of the cycle and, if auditing, compares how the totals changed against the
+    /// budget.
+    ///
+    /// Signal fluids are produced and decay by design, so they are only audited outside of the
+    /// fluid phase. Within it, the other fluids are expected to change by their reactions and
+    /// explosions alone. Energy that dying cells don't return to food is reported on its own, so
+    /// it is left out of the expected change in total energy.
+    fn audited<F>(&mut self, phase: Phase, run: F)
+        where F: FnOnce(&mut Grid)
+    {
+        let tolerance = match self.audit {
+            Some(tolerance) => tolerance,
+            None => return run(self),
+        };
+        let before = self.totals();
+        let budget = self.budget.clone();
+        let sources = if phase == Phase::Fluids {
+            self.tiles.iter().filter(|h| !h.wall).fold([0.0; 4], |mut acc, h| {
+                for (a, s) in acc.iter_mut().zip(&h.solution.sources()) {
+                    *a += *s;
                 }
+                acc
+            })
+        } else {
+            [0.0; 4]
+        };
+
+        run(self);
+
+        let after = self.totals();
+        let flows = self.budget.since(&budget);
+        let mut expected = [0.0; TOTAL_FLUIDS];
+        expected[0] = flows.food_change() * self.consumption;
+        for i in 0..4 {
+            expected[i] += sources[i];
+        }
+        let fluids = if phase == Phase::Fluids { 4 } else { TOTAL_FLUIDS };
+        let death_loss = flows.died - flows.released;
+
+        let mut checks = vec![(Quantity::Cells, flows.cell_change(), after.cells - before.cells),
+                              (Quantity::Total,
+                               flows.spawned - flows.spent - death_loss +
+                               sources[0] / self.consumption,
+                               after.energy(self.consumption) - before.energy(self.consumption))];
+        if phase == Phase::Death {
+            checks.push((Quantity::DeathLoss, 0.0, death_loss));
+        }
+        for i in 0..fluids {
+            checks.push((Quantity::Fluid(i), expected[i], after.fluids[i] - before.fluids[i]));
+        }
+        for (quantity, expected, actual) in checks {
+            if (actual - expected).abs() > tolerance {
+                self.drifts.push(Drift {
+                    phase: phase,
+                    quantity: quantity,
+                    expected: expected,
+                    actual: actual,
+                });
             }
+        }
+    }
+
+    /// The total of every fluid, including what cells store, and of the energy held by cells.
+    fn totals(&self) -> Totals {
+        let mut fluids = [0.0; TOTAL_FLUIDS];
+        for h in &self.tiles {
+            for (t, f) in fluids.iter_mut().zip(&h.solution.fluids) {
+                *t += *f;
+            }
+            if let Some(ref c) = h.cell {
+                for (&fluid, &amount) in PUMPED_FLUIDS.iter().zip(&c.stored) {
+                    fluids[fluid] += amount;
+                }
+            }
+        }
+        Totals {
+            fluids: fluids,
+            cells: self.cell_energy(),
+        }
+    }
+
+    /// Every quantity the audit found drifting in the last cycle.
+    pub fn drifts(&self) -> &[Drift] {
+        &self.drifts
+    }
+
+    fn cycle_spawn(&mut self, rng: &mut Isaac64Rng) {
+        let attempts = if self.spawn_rate >= 1.0 {
+            self.spawn_rate as usize
+        } else if rng.next_f64() < self.spawn_rate {
+            1
         } else {
-            if rng.next_f64() < self.spawn_rate {
-                let tile = rng.gen_range(0, self.width * self.height);
-                if self.tiles[tile].cell.is_none() {
-                    self.tiles[tile].cell = Some(Cell::new(rng));
+            0
+        };
+        for _ in 0..attempts {
+            let (x, y) = self.spawn_location(rng);
+            if self.hex(x, y).cell.is_none() && !self.hex(x, y).wall {
+                let cell = self.spawn_cell(rng);
+                self.budget.spawned += cell.as_ref().map_or(0.0, |c| c.inhale);
+                self.hex_mut(x, y).cell = cell;
+            }
+        }
+    }
+
+    fn spawn_location(&self, rng: &mut Isaac64Rng) -> (usize, usize) {
+        if self.spawn_regions.is_empty() {
+            let tile = rng.gen_range(0, self.width * self.height);
+            (tile % self.width, tile / self.width)
+        } else {
+            // Pick a hex uniformly across all of the regions.
+            let total = self.spawn_regions.iter().fold(0, |acc, r| acc + r.area());
+            let mut choice = rng.gen_range(0, total);
+            for r in &self.spawn_regions {
+                if choice < r.area() {
+                    return ((r.x + choice % r.width) % self.width,
+                            (r.y + choice / r.width) % self.height);
                 }
+                choice -= r.area();
             }
+            unreachable!()
         }
     }
 
-    fn cycle_cells(&mut self) {
+    fn spawn_cell(&self, rng: &mut Isaac64Rng) -> Option<Cell> {
+        match self.spawn_source {
+            SpawnSource::Random => Some(Cell::new(&self.rules, rng)),
+            SpawnSource::Library(ref library) => {
+                library.sample(rng).map(|genome| {
+                    Cell::from_brain(genome.brain.clone(), genome.genes.clone(), rng)
+                })
+            }
+            SpawnSource::Archive => {
+                if self.archive.is_empty() {
+                    None
+                } else {
+                    let (brain, genes) =
+                        self.archive[rng.gen_range(0, self.archive.len())].clone();
+                    Some(Cell::from_brain(brain, genes, rng))
+                }
+            }
+        }
+    }
+
+    /// Adds a spawn region centered on a hex.
+    pub fn add_spawn_region(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        use std::cmp::{max, min};
+        let (width, height) = (min(max(width, 1), self.width), min(max(height, 1), self.height));
+        self.spawn_regions.push(Region {
+            x: (x + self.width - width / 2) % self.width,
+            y: (y + self.height - height / 2) % self.height,
+            width: width,
+            height: height,
+        });
+    }
+
+    /// Places a new cell with the given brain and genes on a hex, replacing any cell or wall that
+    /// was there.
+    pub fn place(&mut self,
+                 x: usize,
+                 y: usize,
+                 brain: controller::Mind,
+                 genes: Genes,
+                 rng: &mut Isaac64Rng) {
+        let cell = Cell::from_brain(brain, genes, rng);
+        self.budget.spawned += cell.inhale;
+        self.hex_mut(x, y).wall = false;
+        self.hex_mut(x, y).cell = Some(cell);
+    }
+
+    /// The energy flows since the budget was last taken, which starts it again from zero.
+    pub fn take_budget(&mut self) -> Budget {
+        mem::replace(&mut self.budget, Budget::default())
+    }
+
+    /// The energy held by all cells.
+    pub fn cell_energy(&self) -> f64 {
+        self.tiles.iter().filter_map(|h| h.cell.as_ref()).fold(0.0, |acc, c| acc + c.inhale)
+    }
+
+    /// The energy held by all food.
+    pub fn food_energy(&self) -> f64 {
+        self.tiles.iter().fold(0.0, |acc, h| acc + h.solution.fluids[0]) / self.consumption
+    }
+
+    pub fn stats(&self) -> Stats {
+        let cells = self.tiles.iter().filter_map(|h| h.cell.as_ref()).collect_vec();
+        Stats {
+            population: cells.len(),
+            distributions: vec![("mutate_probability",
+                                 Distribution::new(cells.iter()
+                                     .map(|c| c.genes.mutate_probability))),
+                                ("mutate_size",
+                                 Distribution::new(cells.iter()
+                                     .map(|c| c.genes.mutate_size as f64))),
+                                ("crossover_points",
+                                 Distribution::new(cells.iter()
+                                     .map(|c| c.genes.crossover_points as f64))),
+                                ("instructions",
+                                 Distribution::new(cells.iter()
+                                     .map(|c| c.brain.instructions() as f64))),
+                                ("registers",
+                                 Distribution::new(cells.iter()
+                                     .map(|c| c.brain.registers() as f64))),
+                                ("temperature",
+                                 Distribution::new(cells.iter().map(|c| c.genes.temperature))),
+                                ("offspring_share",
+                                 Distribution::new(cells.iter()
+                                     .map(|c| c.genes.offspring_share))),
+                                ("ornament",
+                                 Distribution::new(cells.iter().map(|c| c.genes.ornament))),
+                                ("preference",
+                                 Distribution::new(cells.iter().map(|c| c.genes.preference))),
+                                ("tolerance",
+                                 Distribution::new(cells.iter().map(|c| c.genes.tolerance))),
+                                ("kill_tolerance",
+                                 Distribution::new(cells.iter()
+                                     .map(|c| c.genes.kill_tolerance()))),
+                                ("age", Distribution::new(cells.iter().map(|c| c.age as f64))),
+                                ("lifespan",
+                                 Distribution::new(self.lifespans.iter().map(|&l| l as f64)))],
+        }
+    }
+
+    fn archive_cell(&mut self, brain: controller::Mind, genes: Genes) {
+        if self.archive.len() < ARCHIVE_SIZE {
+            self.archive.push((brain, genes));
+        } else {
+            self.archive[self.archive_next] = (brain, genes);
+            self.archive_next = (self.archive_next + 1) % ARCHIVE_SIZE;
+        }
+    }
+
+    fn cycle_cells(&mut self, rng: &mut Isaac64Rng) {
+        let traced = self.trace.as_ref().map(|t| t.id);
+        let rules = self.rules.clone();
+        let rules = &rules;
         let g = GridCont(self as *mut Grid);
         let g = &g;
         let numcpus = num_cpus::get();
+        // Each thread gets its own generator so that choices can be random.
+        let seeds = (0..numcpus).map(|_| rng.gen::<u64>()).collect_vec();
+        let seeds = &seeds;
+        // Let neighbors see what each cell did and the messages it sent last cycle.
         crossbeam::scope(|scope| {
             for i in 0..numcpus {
                 scope.spawn(move || {
                     let g: &mut Grid = unsafe { mem::transmute(g.0) };
                     for x in 0..g.width {
                         for y in (g.height * i / numcpus)..(g.height * (i + 1) / numcpus) {
+                            if let Some(ref mut c) = g.hex_mut(x, y).cell {
+                                c.publish();
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        // The thread that finds the tagged cell returns what it sensed and decided, which is
+        // written to the trace once every thread is done.
+        let record = crossbeam::scope(|scope| {
+            let handles = (0..numcpus).map(|i| {
+                scope.spawn(move || {
+                    let g: &mut Grid = unsafe { mem::transmute(g.0) };
+                    let mut rng = Isaac64Rng::from_seed(&[seeds[i]]);
+                    let mut record = None;
+                    for x in 0..g.width {
+                        for y in (g.height * i / numcpus)..(g.height * (i + 1) / numcpus) {
+                            let distant = if g.hex(x, y).cell.is_some() {
+                                g.distant(x, y, rules.sense_radius)
+                            } else {
+                                [Distant::new(); 6]
+                            };
                             let (this, neighbors) = g.hex_and_neighbors(x, y);
                             this.decision = if let Some(ref mut this_cell) = this.cell {
-                                let neighbor_presents = [neighbors[0].cell.is_some(),
-                                                         neighbors[1].cell.is_some(),
-                                                         neighbors[2].cell.is_some(),
-                                                         neighbors[3].cell.is_some(),
-                                                         neighbors[4].cell.is_some(),
-                                                         neighbors[5].cell.is_some()];
-
-                                Some(this_cell.decide([&this.solution.fluids,
-                                                       &neighbors[0].solution.fluids,
-                                                       &neighbors[1].solution.fluids,
-                                                       &neighbors[2].solution.fluids,
-                                                       &neighbors[3].solution.fluids,
-                                                       &neighbors[4].solution.fluids,
-                                                       &neighbors[5].solution.fluids],
-                                                      &neighbor_presents))
+                                let sensors = this_cell.sense([&this.solution.fluids,
+                                                               &neighbors[0].solution.fluids,
+                                                               &neighbors[1].solution.fluids,
+                                                               &neighbors[2].solution.fluids,
+                                                               &neighbors[3].solution.fluids,
+                                                               &neighbors[4].solution.fluids,
+                                                               &neighbors[5].solution.fluids],
+                                                              [neighbors[0].cell.as_ref(),
+                                                               neighbors[1].cell.as_ref(),
+                                                               neighbors[2].cell.as_ref(),
+                                                               neighbors[3].cell.as_ref(),
+                                                               neighbors[4].cell.as_ref(),
+                                                               neighbors[5].cell.as_ref()],
+                                                              &distant,
+                                                              [neighbors[0].wall,
+                                                               neighbors[1].wall,
+                                                               neighbors[2].wall,
+                                                               neighbors[3].wall,
+                                                               neighbors[4].wall,
+                                                               neighbors[5].wall],
+                                                              rules);
+                                let tagged = traced == Some(this_cell.id);
+                                // The trace needs the memory the brain read, not what it wrote.
+                                let inputs = if tagged {
+                                    trace::inputs(&sensors[..], &this_cell.brain)
+                                } else {
+                                    Vec::new()
+                                };
+                                let mut actions = [0.0; brain::STATIC_OUTPUTS];
+                                let decision =
+                                    this_cell.decide(&sensors[..], &mut actions, rules, &mut rng);
+                                if tagged {
+                                    record = Some(Record {
+                                        x: x,
+                                        y: y,
+                                        inhale: this_cell.inhale,
+                                        inputs: inputs,
+                                        actions: actions[..].to_vec(),
+                                        decision: decision.clone(),
+                                    });
+                                }
+                                Some(decision)
                             } else {
                                 None
                             }
                         }
                     }
-                });
-            }
+                    record
+                })
+            }).collect_vec();
+            handles.into_iter().fold(None, |acc, h| acc.or(h.join()))
         });
+
+        // Every living cell decides, so a tagged cell that didn't has died.
+        match record {
+            Some(record) => {
+                if let Some(ref mut t) = self.trace {
+                    if let Err(e) = t.record(&record) {
+                        println!("Failed to write trace: {}", e);
+                    }
+                }
+            }
+            None => self.trace = None,
+        }
     }
 
     fn cycle_decisions(&mut self, rng: &mut Isaac64Rng) {
@@ -227,12 +734,18 @@ impl Grid {
         let g = &g;
         let explode_amount = self.explode_amount;
         let explode_requirement = self.explode_requirement;
+        let activity_cost = self.activity_cost;
+        let (pump_costs, storage_caps) = (self.pump_costs, self.storage_caps);
+        let pumping = self.rules.pumping;
         let numcpus = num_cpus::get();
-        // Compute the deltas resulting from the decision.
-        crossbeam::scope(|scope| {
-            for i in 0..numcpus {
+        // Compute the deltas resulting from the decision, charge for acting and pump fluids,
+        // collecting what that cost. Every cell has sensed by now, so none sees another's energy
+        // change partway through the cycle.
+        let spent = crossbeam::scope(|scope| {
+            let handles = (0..numcpus).map(|i| {
                 scope.spawn(move || {
                     let g: &mut Grid = unsafe { mem::transmute(g.0) };
+                    let mut spent = 0.0;
                     for x in 0..g.width {
                         for y in (g.height * i / numcpus)..(g.height * (i + 1) / numcpus) {
                             let (width, height) = (g.width, g.height);
@@ -247,8 +760,27 @@ impl Grid {
                                 [NORMAL_DIFFUSION; 6]
                             };
 
-                            // Only add movements here if no cell is present.
-                            if this.cell.is_none() {
+                            if let (Some(c), Some(decision)) = (this.cell.as_mut(),
+                                                                this.decision.as_ref()) {
+                                // Acting costs energy in proportion to how hard the cell tries.
+                                match decision.choice {
+                                    Choice::Nothing => {}
+                                    _ => {
+                                        let cost = (activity_cost * decision.effort).min(c.inhale);
+                                        c.inhale -= cost;
+                                        spent += cost;
+                                    }
+                                }
+                                if pumping {
+                                    spent += c.pump(&mut this.solution.fluids,
+                                                    &decision.pumps,
+                                                    &pump_costs,
+                                                    &storage_caps);
+                                }
+                            }
+
+                            // Only add movements here if no cell or wall is present.
+                            if this.cell.is_none() && !this.wall {
                                 // Add any neighbor movements to the movement_attempts vector.
                                 for (n, &facing) in neighbors.iter().zip(&[Direction::DownLeft,
                                                                            Direction::DownRight,
@@ -313,70 +845,56 @@ impl Grid {
                             }
                         }
                     }
-                });
-            }
+                    spent
+                })
+            }).collect_vec();
+            handles.into_iter().fold(0.0, |acc, h| acc + h.join())
         });
+        self.budget.spent += spent;
 
         // Perform the deltas.
+        let rules = self.rules.clone();
+        if rules.gene_transfer != GeneTransfer::Off {
+            self.cycle_transfers(&rules, rng);
+        }
+        self.cycle_walls();
         for x in 0..self.width {
             for y in 0..self.height {
+                // Nothing can move or be born into a wall built this cycle.
+                let open = !self.hex(x, y).wall;
                 // Handle movement.
-                if self.hex(x, y).delta.movement_attempts.len() == 1 {
+                if open && self.hex(x, y).delta.movement_attempts.len() == 1 {
                     let from_coord = self.hex(x, y).delta.movement_attempts[0];
                     self.hex_mut(x, y).cell = self.hex_mut(from_coord.0, from_coord.1).cell.take();
                     // Apply movement cost.
-                    let inhale = self.hex(x, y).cell.as_ref().unwrap().inhale;
-                    if inhale >= self.movement_cost {
-                        self.hex_mut(x, y).cell.as_mut().unwrap().inhale -= self.movement_cost;
-                    } else {
-                        self.hex_mut(x, y).cell.as_mut().unwrap().inhale = 0;
-                    }
+                    let cost = self.movement_cost;
+                    self.charge((x, y), cost);
                     // Handle mating.
-                } else if self.hex(x, y).delta.mate_attempts.len() == 1 {
+                } else if open && self.hex(x, y).delta.mate_attempts.len() == 1 {
                     let mate = self.hex(x, y).delta.mate_attempts[0].clone();
                     self.hex_mut(x, y).cell = if mate.mate == (x, y) {
                         // Apply movement and divide cost to source.
-                        let inhale =
-                            self.hex(mate.source.0, mate.source.1).cell.as_ref().unwrap().inhale;
-                        if inhale >= self.movement_cost + self.divide_cost {
-                            self.hex_mut(mate.source.0, mate.source.1)
-                                .cell
-                                .as_mut()
-                                .unwrap()
-                                .inhale -= self.movement_cost + self.divide_cost;
-                        } else {
-                            self.hex_mut(mate.source.0, mate.source.1)
-                                .cell
-                                .as_mut()
-                                .unwrap()
-                                .inhale = 0;
-                        }
+                        let cost = self.movement_cost + self.divide_cost;
+                        self.charge(mate.source, cost);
                         Some(self.hex_mut(mate.source.0, mate.source.1)
                             .cell
                             .as_mut()
                             .unwrap()
-                            .divide(rng))
+                            .divide(&rules, rng))
                     } else {
-                        if self.hex(mate.mate.0, mate.mate.1).cell.is_some() {
-                            // Apply movement and divide cost to source.
-                            let inhale = self.hex(mate.source.0, mate.source.1)
-                                .cell
-                                .as_ref()
-                                .unwrap()
-                                .inhale;
-                            if inhale >= self.movement_cost + self.divide_cost {
-                                self.hex_mut(mate.source.0, mate.source.1)
-                                    .cell
-                                    .as_mut()
-                                    .unwrap()
-                                    .inhale -= self.movement_cost + self.divide_cost;
-                            } else {
-                                self.hex_mut(mate.source.0, mate.source.1)
-                                    .cell
-                                    .as_mut()
-                                    .unwrap()
-                                    .inhale = 0;
+                        let accepted = match (&self.hex(mate.source.0, mate.source.1).cell,
+                                              &self.hex(mate.mate.0, mate.mate.1).cell) {
+                            (&Some(ref a), &Some(ref b)) => {
+                                a.accepts(b, &rules) && b.accepts(a, &rules)
                             }
+                            _ => false,
+                        };
+                        if accepted {
+                            // Apply movement and mate cost to source and mate cost to the mate.
+                            let cost = self.movement_cost + self.mate_cost;
+                            self.charge(mate.source, cost);
+                            let cost = self.mate_cost;
+                            self.charge(mate.mate, cost);
                             // This is safe so long as the cells arent the same.
                             Some(unsafe {
                                     mem::transmute::<_,
@@ -385,15 +903,27 @@ impl Grid {
                                 .cell
                                 .as_mut()
                                 .unwrap()
-                                .mate(&self.hex(mate.mate.0, mate.mate.1)
+                                .mate(self.hex_mut(mate.mate.0, mate.mate.1)
                                           .cell
-                                          .as_ref()
+                                          .as_mut()
                                           .unwrap(),
+                                      &rules,
                                       rng))
                         } else {
                             None
                         }
                     };
+
+                    // Occasionally record newborns in the archive.
+                    if rng.next_f64() < ARCHIVE_PROBABILITY {
+                        let cell = self.hex(x, y)
+                            .cell
+                            .as_ref()
+                            .map(|c| (c.brain.clone(), c.genes.clone()));
+                        if let Some((brain, genes)) = cell {
+                            self.archive_cell(brain, genes);
+                        }
+                    }
                 }
 
                 // Clear the decisions.
@@ -402,10 +932,92 @@ impl Grid {
         }
     }
 
+    /// Transfers genes between neighbors before any cells have moved.
+    fn cycle_transfers(&mut self, rules: &Rules, rng: &mut Isaac64Rng) {
+        for x in 0..self.width {
+            for y in 0..self.height {
+                let direction = match self.hex(x, y).decision {
+                    Some(Decision { choice: Choice::Transfer(direction), .. }) => direction,
+                    _ => continue,
+                };
+                let target = in_direction(x, y, self.width, self.height, direction);
+                // Only hexes with cells have decisions.
+                let accepted = match self.hex(target.0, target.1).decision {
+                    Some(ref d) => rules.gene_transfer == GeneTransfer::Forced || d.receptive,
+                    None => false,
+                };
+                if accepted {
+                    let cost = self.transfer_cost;
+                    self.charge((x, y), cost);
+                    let donor = self.hex(x, y).cell.as_ref().unwrap().brain.clone();
+                    self.hex_mut(target.0, target.1)
+                        .cell
+                        .as_mut()
+                        .unwrap()
+                        .brain
+                        .transfer(&donor, rng);
+                }
+            }
+        }
+    }
+
+    /// Builds and digs walls before any cells have moved.
+    fn cycle_walls(&mut self) {
+        for x in 0..self.width {
+            for y in 0..self.height {
+                let (direction, build) = match self.hex(x, y).decision {
+                    Some(Decision { choice: Choice::Build(direction), .. }) => (direction, true),
+                    Some(Decision { choice: Choice::Dig(direction), .. }) => (direction, false),
+                    _ => continue,
+                };
+                let target = in_direction(x, y, self.width, self.height, direction);
+                let possible = {
+                    let hex = self.hex(target.0, target.1);
+                    hex.cell.is_none() && hex.wall != build
+                };
+                if possible {
+                    let cost = self.wall_cost;
+                    self.charge((x, y), cost);
+                    self.hex_mut(target.0, target.1).wall = build;
+                }
+            }
+        }
+    }
+
+    /// Takes inhale from the cell at a hex, leaving it with none if it can't afford all of it.
+    fn charge(&mut self, (x, y): (usize, usize), cost: f64) {
+        let spent = {
+            let cell = self.hex_mut(x, y).cell.as_mut().unwrap();
+            let spent = cost.min(cell.inhale);
+            cell.inhale -= spent;
+            spent
+        };
+        self.budget.spent += spent;
+    }
+
     fn cycle_fluids(&mut self) {
         let g = GridCont(self as *mut Grid);
         let g = &g;
         let numcpus = num_cpus::get();
+        // Walls block diffusion to and from them.
+        crossbeam::scope(|scope| {
+            for i in 0..numcpus {
+                scope.spawn(move || {
+                    let g: &mut Grid = unsafe { mem::transmute(g.0) };
+                    for x in 0..g.width {
+                        for y in (g.height * i / numcpus)..(g.height * (i + 1) / numcpus) {
+                            let (this, neighbors) = g.hex_and_neighbors(x, y);
+                            for (d, n) in neighbors.iter().enumerate() {
+                                if this.wall || n.wall {
+                                    this.solution.coefficients[d] = [0.0; TOTAL_FLUIDS];
+                                }
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
         // Then update diffusion.
         crossbeam::scope(|scope| {
             for i in 0..numcpus {
@@ -438,7 +1050,12 @@ impl Grid {
                     let g: &mut Grid = unsafe { mem::transmute(g.0) };
                     for x in 0..g.width {
                         for y in (g.height * i / numcpus)..(g.height * (i + 1) / numcpus) {
-                            g.hex_mut(x, y).solution.end_cycle();
+                            let this = g.hex_mut(x, y);
+                            if this.wall {
+                                this.solution.hold();
+                            } else {
+                                this.solution.end_cycle();
+                            }
                         }
                     }
                 });
@@ -446,61 +1063,113 @@ impl Grid {
         });
     }
 
-    fn cycle_death(&mut self) {
+    fn cycle_death(&mut self, rng: &mut Isaac64Rng) {
         let g = GridCont(self as *mut Grid);
         let g = &g;
         let numcpus = num_cpus::get();
         let consumption = self.consumption;
-        // Finish the cycle.
-        crossbeam::scope(|scope| {
-            for i in 0..numcpus {
+        let senescence = self.rules.senescence;
+        let kill_bounds = self.rules.kill_bounds;
+        let seeds = (0..numcpus).map(|_| rng.gen::<u64>()).collect_vec();
+        let seeds = &seeds;
+        // Finish the cycle, collecting the energy flows and the ages of cells that died.
+        let results = crossbeam::scope(|scope| {
+            let handles = (0..numcpus).map(|i| {
                 scope.spawn(move || {
                     let g: &mut Grid = unsafe { mem::transmute(g.0) };
+                    let mut rng = Isaac64Rng::from_seed(&[seeds[i]]);
+                    let mut lifespans = Vec::new();
+                    let mut budget = Budget::default();
                     let inhale_minimum = g.inhale_minimum;
                     let inhale_cap = g.inhale_cap;
+                    let intake_rate = g.intake_rate;
+                    let metabolism = g.metabolism;
                     let death_release_coefficient = g.death_release_coefficient;
+                    let (instruction_cost, register_cost) = (g.instruction_cost, g.register_cost);
+                    let kill_tolerance_cost = g.kill_tolerance_cost;
                     for x in 0..g.width {
                         for y in (g.height * i / numcpus)..(g.height * (i + 1) / numcpus) {
                             let hex = g.hex_mut(x, y);
-                            if hex.cell.is_some() {
-                                if hex.cell.as_ref().unwrap().suicide ||
-                                   hex.solution.fluids[3] > KILL_FLUID_UPPER_THRESHOLD ||
-                                   hex.solution.fluids[3] < KILL_FLUID_LOWER_THRESHOLD ||
-                                   hex.cell.as_ref().unwrap().inhale < inhale_minimum {
-                                    hex.solution.fluids[0] +=
-                                        death_release_coefficient * consumption *
-                                        hex.cell.as_ref().unwrap().inhale as f64;
-                                    hex.cell = None;
-                                } else if hex.solution.fluids[0] <= consumption {
-                                    if hex.cell.as_ref().unwrap().inhale != 0 {
-                                        hex.cell.as_mut().unwrap().inhale -= 1;
-                                    } else {
-                                        hex.cell = None;
+                            let mut aged_out = false;
+                            if let Some(ref mut c) = hex.cell {
+                                c.age += 1;
+                                // Living, genome complexity, kill fluid tolerance and age all cost
+                                // energy.
+                                let mut cost = metabolism +
+                                               instruction_cost * c.brain.instructions() as f64 +
+                                               register_cost * c.brain.registers() as f64;
+                                if kill_bounds == KillBounds::Heritable {
+                                    cost += kill_tolerance_cost * c.genes.kill_tolerance();
+                                }
+                                match senescence {
+                                    Senescence::Off => {}
+                                    Senescence::Cost(rate) => cost += rate * c.age as f64,
+                                    Senescence::Mortality(rate) => {
+                                        aged_out = rng.next_f64() < rate * c.age as f64;
                                     }
-                                } else {
-                                    hex.solution.fluids[0] -= consumption;
-                                    // NOTE: This used to be survival threshold.
-                                    if hex.solution.fluids[0] < 0.0 {
-                                        if hex.cell.as_ref().unwrap().inhale != 0 {
-                                            hex.cell.as_mut().unwrap().inhale -= 1;
-                                        } else {
-                                            hex.solution.fluids[0] +=
-                                                death_release_coefficient * consumption *
-                                                hex.cell.as_ref().unwrap().inhale as f64;
-                                            hex.cell = None;
+                                }
+                                let cost = cost.min(c.inhale);
+                                c.inhale -= cost;
+                                budget.spent += cost;
+
+                                // Cells eat a share of the food in their hex until they are full.
+                                let eaten = (intake_rate * hex.solution.fluids[0])
+                                    .min((inhale_cap - c.inhale) * consumption)
+                                    .max(0.0);
+                                hex.solution.fluids[0] -= eaten;
+                                c.inhale += eaten / consumption;
+                                budget.eaten += eaten / consumption;
+                            }
+                            let dead = match hex.cell {
+                                Some(ref c) => {
+                                    let (lower, upper) = match kill_bounds {
+                                        KillBounds::Fixed => {
+                                            (KILL_FLUID_LOWER_THRESHOLD, KILL_FLUID_UPPER_THRESHOLD)
                                         }
-                                    } else {
-                                        if hex.cell.as_ref().unwrap().inhale < inhale_cap {
-                                            hex.cell.as_mut().unwrap().inhale += 1;
+                                        KillBounds::Heritable => {
+                                            (c.genes.kill_lower, c.genes.kill_upper)
                                         }
-                                    }
+                                    };
+                                    c.suicide || aged_out || hex.solution.fluids[3] > upper ||
+                                    hex.solution.fluids[3] < lower ||
+                                    c.inhale < inhale_minimum
                                 }
+                                None => false,
+                            };
+                            if dead {
+                                let c = hex.cell.take().unwrap();
+                                // Stored fluids spill back into the hex.
+                                for (&fluid, &amount) in PUMPED_FLUIDS.iter().zip(&c.stored) {
+                                    hex.solution.fluids[fluid] += amount;
+                                }
+                                hex.solution.fluids[0] +=
+                                    death_release_coefficient * consumption * c.inhale;
+                                budget.died += c.inhale;
+                                budget.released += death_release_coefficient * c.inhale;
+                                lifespans.push(c.age);
                             }
                         }
                     }
-                });
-            }
+                    (lifespans, budget)
+                })
+            }).collect_vec();
+            handles.into_iter().map(|h| h.join()).collect_vec()
         });
+        for (lifespans, budget) in results {
+            self.budget.add(&budget);
+            for lifespan in lifespans {
+                self.record_lifespan(lifespan);
+            }
+        }
+    }
+
+    fn record_lifespan(&mut self, lifespan: u64) {
+        if self.lifespans.len() < LIFESPAN_HISTORY {
+            self.lifespans.push(lifespan);
+        } else {
+            self.lifespans[self.lifespans_next] = lifespan;
+            self.lifespans_next = (self.lifespans_next + 1) % LIFESPAN_HISTORY;
+        }
     }
 }
 
@@ -522,6 +1191,7 @@ fn randomizing_vec(width: usize, height: usize, rng: &mut Isaac64Rng) -> Vec<Hex
                                         [NORMAL_DIFFUSION; 6]),
                 cell: None,
                 decision: None,
+                wall: false,
                 delta: Delta {
                     movement_attempts: Vec::with_capacity(6),
                     mate_attempts: Vec::with_capacity(6),
@@ -531,6 +1201,64 @@ fn randomizing_vec(width: usize, height: usize, rng: &mut Isaac64Rng) -> Vec<Hex
         .collect_vec()
 }
 
+/// Iterates the ring of hexes at a distance from a center hex.
+///
+/// Sector `i` starts at the hex `radius` steps away in `DIRECTIONS[i]` and runs counterclockwise
+/// up to the start of the next sector, so every sector holds `radius` hexes.
+pub struct Ring {
+    position: (usize, usize),
+    width: usize,
+    height: usize,
+    radius: usize,
+    sector: usize,
+    step: usize,
+}
+
+impl Ring {
+    fn new(x: usize, y: usize, width: usize, height: usize, radius: usize) -> Self {
+        let mut position = (x, y);
+        for _ in 0..radius {
+            position = in_direction(position.0, position.1, width, height, DIRECTIONS[0]);
+        }
+        Ring {
+            position: position,
+            width: width,
+            height: height,
+            radius: radius,
+            // A ring of radius 0 is only the center, which isn't in any sector.
+            sector: if radius == 0 {
+                6
+            } else {
+                0
+            },
+            step: 0,
+        }
+    }
+}
+
+impl Iterator for Ring {
+    type Item = (usize, (usize, usize));
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.sector == 6 {
+            return None;
+        }
+        let item = (self.sector, self.position);
+        // The side of the ring from one corner to the next runs two directions further around.
+        self.position = in_direction(self.position.0,
+                                     self.position.1,
+                                     self.width,
+                                     self.height,
+                                     DIRECTIONS[(self.sector + 2) % 6]);
+        self.step += 1;
+        if self.step == self.radius {
+            self.step = 0;
+            self.sector += 1;
+        }
+        Some(item)
+    }
+}
+
 fn in_direction(x: usize,
                 y: usize,
                 width: usize,
@@ -541,3 +1269,104 @@ fn in_direction(x: usize,
     (((width + x) as isize + diff.0) as usize % width,
      ((height + y) as isize + diff.1) as usize % height)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::cell::{GeneTransfer, KillBounds, DIRECTIONS};
+    use itertools::Itertools;
+    use rand::{Isaac64Rng, SeedableRng};
+    use std::collections::{HashMap, HashSet, VecDeque};
+
+    /// Steps from a center hex to every hex within a distance, found by walking neighbors.
+    fn distances(x: usize,
+                 y: usize,
+                 width: usize,
+                 height: usize,
+                 max: usize)
+                 -> HashMap<(usize, usize), usize> {
+        let mut distances = HashMap::new();
+        let mut queue = VecDeque::new();
+        distances.insert((x, y), 0);
+        queue.push_back((x, y));
+        while let Some(p) = queue.pop_front() {
+            let d = distances[&p];
+            if d == max {
+                continue;
+            }
+            for &direction in &DIRECTIONS {
+                let n = in_direction(p.0, p.1, width, height, direction);
+                if !distances.contains_key(&n) {
+                    distances.insert(n, d + 1);
+                    queue.push_back(n);
+                }
+            }
+        }
+        distances
+    }
+
+    #[test]
+    fn rings_split_evenly_into_sectors() {
+        let (width, height) = (32, 32);
+        for &(x, y) in &[(16, 16), (15, 17), (0, 0)] {
+            let distances = distances(x, y, width, height, 8);
+            for radius in 1..8 {
+                let ring = Ring::new(x, y, width, height, radius).collect::<Vec<_>>();
+                assert_eq!(ring.len(), 6 * radius);
+                let hexes = ring.iter().map(|&(_, h)| h).collect::<HashSet<_>>();
+                assert_eq!(hexes.len(), 6 * radius);
+                for h in &hexes {
+                    assert_eq!(distances[h], radius);
+                }
+                for sector in 0..6 {
+                    assert_eq!(ring.iter().filter(|&&(s, _)| s == sector).count(), radius);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn audit_finds_no_drift() {
+        let mut rng = Isaac64Rng::from_seed(&[7, 7, 7, 7]);
+        let mut g = Grid::new(16,
+                              16,
+                              0.04,
+                              2.0,
+                              500.0,
+                              10000.0,
+                              0.001,
+                              1.0,
+                              0.5,
+                              1.0,
+                              5.0,
+                              5.0,
+                              5.0,
+                              20.0,
+                              2100.0,
+                              1.0,
+                              0.5,
+                              0.001,
+                              0.05,
+                              100.0,
+                              [1.0, 100.0, 1000.0],
+                              [400.0, 10.0, 1.0],
+                              &mut rng);
+        // Turn on every rule that moves energy or fluid around, and give cells food to eat.
+        g.rules.gene_transfer = GeneTransfer::Voluntary;
+        g.rules.kill_bounds = KillBounds::Heritable;
+        g.rules.pumping = true;
+        g.rules.terrain = true;
+        for h in &mut g.tiles {
+            h.solution.fluids[0] = 100.0;
+        }
+        g.audit = Some(0.001);
+        for cycle in 0..50 {
+            g.cycle(&mut rng);
+            assert!(g.drifts().is_empty(),
+                    "cycle {}: {}",
+                    cycle,
+                    g.drifts().iter().map(|d| d.to_string()).join("; "));
+        }
+        assert!(g.cell_energy() > 0.0);
+    }
+}
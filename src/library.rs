@@ -0,0 +1,58 @@
+use super::cell::controller::Mind;
+use super::cell::genes::Genes;
+use std::fs::File;
+use std::io;
+use rand::{Isaac64Rng, Rng};
+use bincode;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Genome {
+    pub name: String,
+    pub brain: Mind,
+    pub genes: Genes,
+}
+
+/// A collection of named genomes that can be saved to and loaded from disk.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Library {
+    pub genomes: Vec<Genome>,
+}
+
+impl Library {
+    /// Loads a library, giving `None` if there is no file at the path yet.
+    pub fn load(path: &str) -> Result<Option<Library>, String> {
+        let mut f = match File::open(path) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Unable to open file \"{}\": {}", path, e)),
+        };
+        bincode::serde::deserialize_from(&mut f, bincode::SizeLimit::Infinite)
+            .map(Some)
+            .map_err(|e| format!("Failed to load library \"{}\": {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut f = try!(File::create(path)
+            .map_err(|e| format!("Unable to create file \"{}\": {}", path, e)));
+        bincode::serde::serialize_into(&mut f, self, bincode::SizeLimit::Infinite)
+            .map_err(|e| format!("Failed to save library \"{}\": {}", path, e))
+    }
+
+    /// Adds a genome to the library and returns its index.
+    pub fn add(&mut self, name: String, brain: Mind, genes: Genes) -> usize {
+        self.genomes.push(Genome {
+            name: name,
+            brain: brain,
+            genes: genes,
+        });
+        self.genomes.len() - 1
+    }
+
+    pub fn sample(&self, rng: &mut Isaac64Rng) -> Option<&Genome> {
+        if self.genomes.is_empty() {
+            None
+        } else {
+            Some(&self.genomes[rng.gen_range(0, self.genomes.len())])
+        }
+    }
+}
@@ -21,8 +21,12 @@ extern crate num_cpus;
 extern crate crossbeam;
 
 mod cell;
+mod energy;
 mod fluid;
 mod grid;
+mod library;
+mod stats;
+mod trace;
 
 use gg::render2::*;
 use nalgebra as na;
@@ -31,6 +35,7 @@ use num::One;
 
 use std::time;
 use std::fs::File;
+use std::io::Write;
 use std::sync::mpsc::channel;
 
 use rand::{Isaac64Rng, SeedableRng};
@@ -42,16 +47,35 @@ const GRID_HEIGHT: usize = 125 * 5 / 2;
 const DEFAULT_CONSUMPTION: f64 = 0.04;
 const SPAWN_DENSITY: f64 = 0.00001;
 const DEFAULT_SPAWN_RATE: f64 = SPAWN_DENSITY * GRID_WIDTH as f64 * GRID_HEIGHT as f64;
-const DEFAULT_INHALE_MINIMUM: usize = 500;
-const DEFAULT_INHALE_CAP: usize = 10000;
-const DEFAULT_MOVEMENT_COST: usize = 0;
-const DEFAULT_DIVIDE_COST: usize = 5;
-
-const DEFAULT_EXPLODE_REQUIREMENT: usize = 2100;
+const DEFAULT_INHALE_MINIMUM: f64 = 500.0;
+const DEFAULT_INHALE_CAP: f64 = 10000.0;
+const DEFAULT_INTAKE_RATE: f64 = 0.001;
+const DEFAULT_METABOLISM: f64 = 1.0;
+// Scaled by the effort of the chosen action, so acting at full strength costs half the metabolism.
+const DEFAULT_ACTIVITY_COST: f64 = 0.5;
+const DEFAULT_MOVEMENT_COST: f64 = 0.0;
+const DEFAULT_DIVIDE_COST: f64 = 5.0;
+const DEFAULT_MATE_COST: f64 = 5.0;
+const DEFAULT_TRANSFER_COST: f64 = 5.0;
+const DEFAULT_WALL_COST: f64 = 20.0;
+
+const DEFAULT_EXPLODE_REQUIREMENT: f64 = 2100.0;
 const DEFAULT_EXPLODE_AMOUNT: f64 = 0.5;
 
 const DEFAULT_DEATH_RELEASE_COEFFICIENT: f64 = 1.0;
 
+const DEFAULT_INSTRUCTION_COST: f64 = 0.0;
+const DEFAULT_REGISTER_COST: f64 = 0.0;
+// The genome costs that toggling complexity costs switches to.
+const INSTRUCTION_COST: f64 = 0.001;
+const REGISTER_COST: f64 = 0.05;
+// Per unit of kill fluid tolerated when the bounds are heritable, so the starting range costs 0.4 a
+// cycle.
+const DEFAULT_KILL_TOLERANCE_COST: f64 = 100.0;
+// Per unit of food, B and kill fluid pumped, and how much of each a cell can store.
+const DEFAULT_PUMP_COSTS: [f64; fluid::TOTAL_PUMPED] = [1.0, 100.0, 1000.0];
+const DEFAULT_STORAGE_CAPS: [f64; fluid::TOTAL_PUMPED] = [400.0, 10.0, 1.0];
+
 // TODO: Figure out when lines are used and set it correctly.
 const SCROLL_LINES_RATIO: f32 = 0.707;
 const SCROLL_PIXELS_RATIO: f32 = 0.707;
@@ -60,8 +84,32 @@ const GRID_SPAWN_MULTIPLY: f64 = 1.25;
 
 const SECONDS_BETWEEN_AUTOSAVES: u64 = 60 * 30;
 
+const SECONDS_BETWEEN_STATS: u64 = 60;
+const STATS_FILE: &'static str = "stats.csv";
+
 const MANUAL_FEED_AMOUNT: f64 = 500000.0;
 
+const LIBRARY_FILE: &'static str = "library";
+
+const DOT_FILE: &'static str = "brain.dot";
+
+const SPAWN_REGION_SIZE: usize = 32;
+
+const MAX_MATING_TYPES: u32 = 4;
+// The share of inhale parents give their offspring when the split isn't heritable.
+const FIXED_OFFSPRING_SHARE: f64 = 0.5;
+// Senescence rates per cycle of age.
+const AGE_COST: f64 = 0.0001;
+const AGE_MORTALITY: f64 = 0.000001;
+// How far a total can drift in one phase before the audit reports it.
+const AUDIT_TOLERANCE: f64 = 0.001;
+
+// Cells whose sensors are sampled to probe a brain.
+const SENSITIVITY_SAMPLES: usize = 64;
+const SENSITIVITY_NUDGE: f64 = 1.0;
+// How many of the strongest input to output effects are printed.
+const SENSITIVITY_REPORT: usize = 20;
+
 // Ratio of width/height in a 2d circle tight-pack or a hex grid.
 const WIDTH_HEIGHT_RATIO: f32 = 0.86602540378;
 
@@ -84,11 +132,22 @@ fn main() {
                                     DEFAULT_SPAWN_RATE,
                                     DEFAULT_INHALE_MINIMUM,
                                     DEFAULT_INHALE_CAP,
+                                    DEFAULT_INTAKE_RATE,
+                                    DEFAULT_METABOLISM,
+                                    DEFAULT_ACTIVITY_COST,
                                     DEFAULT_MOVEMENT_COST,
                                     DEFAULT_DIVIDE_COST,
+                                    DEFAULT_MATE_COST,
+                                    DEFAULT_TRANSFER_COST,
+                                    DEFAULT_WALL_COST,
                                     DEFAULT_EXPLODE_REQUIREMENT,
                                     DEFAULT_DEATH_RELEASE_COEFFICIENT,
                                     DEFAULT_EXPLODE_AMOUNT,
+                                    DEFAULT_INSTRUCTION_COST,
+                                    DEFAULT_REGISTER_COST,
+                                    DEFAULT_KILL_TOLERANCE_COST,
+                                    DEFAULT_PUMP_COSTS,
+                                    DEFAULT_STORAGE_CAPS,
                                     &mut rng)
                 }
             }
@@ -100,11 +159,22 @@ fn main() {
                             DEFAULT_SPAWN_RATE,
                             DEFAULT_INHALE_MINIMUM,
                             DEFAULT_INHALE_CAP,
+                            DEFAULT_INTAKE_RATE,
+                            DEFAULT_METABOLISM,
+                            DEFAULT_ACTIVITY_COST,
                             DEFAULT_MOVEMENT_COST,
                             DEFAULT_DIVIDE_COST,
+                            DEFAULT_MATE_COST,
+                            DEFAULT_TRANSFER_COST,
+                            DEFAULT_WALL_COST,
                             DEFAULT_EXPLODE_REQUIREMENT,
                             DEFAULT_DEATH_RELEASE_COEFFICIENT,
                             DEFAULT_EXPLODE_AMOUNT,
+                            DEFAULT_INSTRUCTION_COST,
+                            DEFAULT_REGISTER_COST,
+                            DEFAULT_KILL_TOLERANCE_COST,
+                            DEFAULT_PUMP_COSTS,
+                            DEFAULT_STORAGE_CAPS,
                             &mut rng)
         }
     };
@@ -121,6 +191,21 @@ fn main() {
     let mut rendering_enabled = true;
 
     let mut last_autosave = time::Instant::now();
+    let mut last_stats = time::Instant::now();
+
+    // A library that exists but can't be loaded is never saved over, so its genomes aren't lost.
+    let (mut library, library_writable) = match library::Library::load(LIBRARY_FILE) {
+        Ok(library) => (library.unwrap_or_else(Default::default), true),
+        Err(e) => {
+            println!("{}", e);
+            println!("The library will not be saved until \"{}\" is moved aside.",
+                     LIBRARY_FILE);
+            (Default::default(), false)
+        }
+    };
+    let mut library_choice = 0;
+    let mut selected_hex = None;
+    let mut traced = None;
 
     loop {
         use glium::Surface;
@@ -224,6 +309,15 @@ fn main() {
 
         g.cycle(&mut rng);
 
+        if let Some(summary) = energy::summary(g.drifts()) {
+            println!("Audit: {}", summary);
+        }
+
+        if traced.is_some() && g.tagged().is_none() {
+            println!("Traced cell {} died", traced.unwrap());
+            traced = None;
+        }
+
         // Don't even vsync if rendering is disabled.
         if rendering_enabled {
             target.unwrap().finish().unwrap();
@@ -244,6 +338,13 @@ fn main() {
             }
         }
 
+        if now - last_stats > time::Duration::from_secs(SECONDS_BETWEEN_STATS) {
+            last_stats = now;
+            if let Err(e) = g.stats().append_to(STATS_FILE) {
+                println!("Failed to write stats to \"{}\": {}", STATS_FILE, e);
+            }
+        }
+
         for ev in display.poll_events() {
             use glium::glutin::{Event, ElementState, MouseButton, MouseScrollDelta,
                                 VirtualKeyCode as VKC};
@@ -280,25 +381,265 @@ fn main() {
                     }
                 }
                 Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::F)) => {
-                    let relative_coord = (last_mouse_pos.0 as f32 - center_mouse_coord.0,
-                                          last_mouse_pos.1 as f32 - center_mouse_coord.1);
-
-                    let hex = (center.0 + relative_coord.0 * hex_per_width_pixel,
-                               center.1 - relative_coord.1 * hex_per_height_pixel);
-                    // Adjust the width based on the height.
-                    let hex = (if hex.1 as isize % 2 == 0 {
-                        hex.0 - 0.25
-                    } else {
-                        hex.0 + 0.25
-                    },
-                               hex.1);
-                    if hex.0 > 0.0 && hex.0 < g.width as f32 && hex.1 > 0.0 &&
-                       hex.1 < g.height as f32 {
-                        let hex = g.hex_mut(hex.0 as usize, hex.1 as usize);
+                    if let Some((x, y)) = mouse_hex(&g,
+                                                    last_mouse_pos,
+                                                    center_mouse_coord,
+                                                    center,
+                                                    (hex_per_width_pixel, hex_per_height_pixel)) {
+                        let hex = g.hex_mut(x, y);
                         hex.solution.fluids[0] += MANUAL_FEED_AMOUNT;
                         println!("New food: {}", hex.solution.fluids[0]);
                     }
                 }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::G)) => {
+                    g.spawn_source = match g.spawn_source {
+                        grid::SpawnSource::Random => {
+                            if library.genomes.is_empty() {
+                                println!("The library is empty.");
+                                grid::SpawnSource::Archive
+                            } else {
+                                grid::SpawnSource::Library(library.clone())
+                            }
+                        }
+                        grid::SpawnSource::Library(_) => grid::SpawnSource::Archive,
+                        grid::SpawnSource::Archive => grid::SpawnSource::Random,
+                    };
+                    println!("Spawning from {}",
+                             match g.spawn_source {
+                                 grid::SpawnSource::Random => "random brains",
+                                 grid::SpawnSource::Library(_) => "the library",
+                                 grid::SpawnSource::Archive => "the archive",
+                             });
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::K)) => {
+                    if let Some((x, y)) = selected_hex {
+                        if let Some(ref c) = g.hex(x, y).cell {
+                            // Asking for a name would stall the window, so the cell names it.
+                            let name = format!("cell-{:x}-age-{}", c.id, c.age);
+                            library_choice = library.add(name, c.brain.clone(), c.genes.clone());
+                            if !library_writable {
+                                println!("Added \"{}\" to the library without saving it.",
+                                         library.genomes[library_choice].name);
+                            } else {
+                                match library.save(LIBRARY_FILE) {
+                                    Ok(()) => {
+                                        println!("Saved \"{}\" to the library.",
+                                                 library.genomes[library_choice].name)
+                                    }
+                                    Err(e) => println!("{}", e),
+                                }
+                            }
+                        }
+                    }
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::B)) => {
+                    if let Some((x, y)) = selected_hex {
+                        if let Some(cell::Cell { brain: cell::controller::Mind::Mep(ref b), .. }) =
+                               g.hex(x, y).cell {
+                            print!("{}", cell::disasm::disassemble(b));
+                        }
+                    }
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::H)) => {
+                    if let Some((x, y)) = selected_hex {
+                        if let Some(cell::Cell { brain: cell::controller::Mind::Mep(ref b), .. }) =
+                               g.hex(x, y).cell {
+                            use cell::sensitivity::{Probe, Sensitivity};
+                            let samples = g.sample_sensors(SENSITIVITY_SAMPLES, &mut rng);
+                            for &(name, probe) in &[("Zeroed", Probe::Zero),
+                                                    ("Nudged", Probe::Nudge(SENSITIVITY_NUDGE))] {
+                                println!("{} inputs:", name);
+                                let sensitivity = Sensitivity::new(b, &samples[..], probe);
+                                for (input, output, change) in
+                                    sensitivity.strongest(SENSITIVITY_REPORT) {
+                                    println!("{} -> {}: {:.3}", input, output, change);
+                                }
+                            }
+                        }
+                    }
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::A)) => {
+                    // Cycle through ablating nothing and then each channel on its own.
+                    let next = match g.rules.ablated.first() {
+                        None => Some(0),
+                        Some(c) => {
+                            cell::CHANNELS.iter().position(|n| n == c).map(|i| i + 1)
+                        }
+                    };
+                    g.rules.ablated = next.and_then(|i| cell::CHANNELS.get(i))
+                        .cloned()
+                        .into_iter()
+                        .collect();
+                    println!("Ablated channels: {:?}", g.rules.ablated);
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::Tab)) => {
+                    use cell::ArbitrationPolicy;
+                    g.rules.arbitration = match g.rules.arbitration {
+                        ArbitrationPolicy::Argmax => ArbitrationPolicy::Softmax,
+                        ArbitrationPolicy::Softmax => ArbitrationPolicy::Threshold,
+                        ArbitrationPolicy::Threshold => ArbitrationPolicy::Argmax,
+                    };
+                    println!("Arbitration: {:?}", g.rules.arbitration);
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::Key0)) => {
+                    use cell::KillBounds;
+                    g.rules.kill_bounds = match g.rules.kill_bounds {
+                        KillBounds::Fixed => KillBounds::Heritable,
+                        KillBounds::Heritable => KillBounds::Fixed,
+                    };
+                    println!("Kill bounds: {:?} ({} per unit of tolerance)",
+                             g.rules.kill_bounds,
+                             g.kill_tolerance_cost);
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::Key1)) => {
+                    // Cycle between no mating types and two up to the maximum.
+                    g.rules.mating_types = match g.rules.mating_types {
+                        n if n >= MAX_MATING_TYPES => 0,
+                        0 => 2,
+                        n => n + 1,
+                    };
+                    println!("Mating types: {}", g.rules.mating_types);
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::Key2)) => {
+                    g.rules.mate_choice = !g.rules.mate_choice;
+                    println!("Mate choice {}",
+                             if g.rules.mate_choice {
+                                 "enabled"
+                             } else {
+                                 "disabled"
+                             });
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::Key3)) => {
+                    use cell::GeneTransfer;
+                    g.rules.gene_transfer = match g.rules.gene_transfer {
+                        GeneTransfer::Off => GeneTransfer::Voluntary,
+                        GeneTransfer::Voluntary => GeneTransfer::Forced,
+                        GeneTransfer::Forced => GeneTransfer::Off,
+                    };
+                    println!("Gene transfer: {:?}", g.rules.gene_transfer);
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::Key4)) => {
+                    use cell::Senescence;
+                    g.rules.senescence = match g.rules.senescence {
+                        Senescence::Off => Senescence::Cost(AGE_COST),
+                        Senescence::Cost(_) => Senescence::Mortality(AGE_MORTALITY),
+                        Senescence::Mortality(_) => Senescence::Off,
+                    };
+                    println!("Senescence: {:?}", g.rules.senescence);
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::Key5)) => {
+                    g.audit = match g.audit {
+                        Some(_) => None,
+                        None => Some(AUDIT_TOLERANCE),
+                    };
+                    println!("Conservation audit: {:?}", g.audit);
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::Key6)) => {
+                    use cell::EnergySplit;
+                    g.rules.energy_split = match g.rules.energy_split {
+                        EnergySplit::Fixed(_) => EnergySplit::Heritable,
+                        EnergySplit::Heritable => EnergySplit::Fixed(FIXED_OFFSPRING_SHARE),
+                    };
+                    println!("Energy split: {:?}", g.rules.energy_split);
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::Key7)) => {
+                    if g.instruction_cost == 0.0 && g.register_cost == 0.0 {
+                        g.instruction_cost = INSTRUCTION_COST;
+                        g.register_cost = REGISTER_COST;
+                    } else {
+                        g.instruction_cost = 0.0;
+                        g.register_cost = 0.0;
+                    }
+                    println!("Complexity costs: {} per instruction, {} per register",
+                             g.instruction_cost,
+                             g.register_cost);
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::Key8)) => {
+                    g.rules.pumping = !g.rules.pumping;
+                    println!("Pumping {}",
+                             if g.rules.pumping {
+                                 "enabled"
+                             } else {
+                                 "disabled"
+                             });
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::Key9)) => {
+                    g.rules.terrain = !g.rules.terrain;
+                    println!("Terrain {}",
+                             if g.rules.terrain {
+                                 "enabled"
+                             } else {
+                                 "disabled"
+                             });
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::Return)) => {
+                    if traced.is_some() {
+                        g.untag();
+                        println!("Stopped tracing cell {}", traced.unwrap());
+                        traced = None;
+                    } else if let Some((x, y)) = selected_hex {
+                        let id = g.hex(x, y).cell.as_ref().map(|c| c.id);
+                        if let Some(id) = id {
+                            let path = format!("trace-{}.csv", id);
+                            match g.tag(id, &path) {
+                                Ok(()) => {
+                                    println!("Tracing cell {} to {}", id, path);
+                                    traced = Some(id);
+                                }
+                                Err(e) => println!("Failed to create trace file: {}", e),
+                            }
+                        }
+                    }
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::V)) => {
+                    if let Some((x, y)) = selected_hex {
+                        if let Some(cell::Cell { brain: cell::controller::Mind::Mep(ref b), .. }) =
+                               g.hex(x, y).cell {
+                            match File::create(DOT_FILE) {
+                                Ok(mut f) => {
+                                    match f.write_all(cell::disasm::dot(b).as_bytes()) {
+                                        Ok(()) => println!("Wrote brain graph to \"{}\".", DOT_FILE),
+                                        Err(e) => println!("Failed to write brain graph: {}", e),
+                                    }
+                                }
+                                Err(e) => println!("Unable to create file \"{}\": {}", DOT_FILE, e),
+                            }
+                        }
+                    }
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::N)) => {
+                    if !library.genomes.is_empty() {
+                        library_choice = (library_choice + 1) % library.genomes.len();
+                        println!("Selected \"{}\" from the library.",
+                                 library.genomes[library_choice].name);
+                    }
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::J)) => {
+                    if let Some((x, y)) = mouse_hex(&g,
+                                                    last_mouse_pos,
+                                                    center_mouse_coord,
+                                                    center,
+                                                    (hex_per_width_pixel, hex_per_height_pixel)) {
+                        if let Some(genome) = library.genomes.get(library_choice) {
+                            g.place(x, y, genome.brain.clone(), genome.genes.clone(), &mut rng);
+                            println!("Placed \"{}\".", genome.name);
+                        }
+                    }
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::Z)) => {
+                    if let Some((x, y)) = mouse_hex(&g,
+                                                    last_mouse_pos,
+                                                    center_mouse_coord,
+                                                    center,
+                                                    (hex_per_width_pixel, hex_per_height_pixel)) {
+                        g.add_spawn_region(x, y, SPAWN_REGION_SIZE, SPAWN_REGION_SIZE);
+                        println!("Spawn regions: {}", g.spawn_regions.len());
+                    }
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::X)) => {
+                    g.spawn_regions.clear();
+                    println!("Spawn regions cleared");
+                }
                 Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::U)) => {
                     g.spawn_rate *= GRID_SPAWN_MULTIPLY;
                     println!("New spawn rate: {}", g.spawn_rate);
@@ -308,15 +649,62 @@ fn main() {
                     println!("New spawn rate: {}", g.spawn_rate);
                 }
                 Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::P)) => {
-                    g.movement_cost += 1;
+                    g.movement_cost += 1.0;
                     println!("New movement cost: {}", g.movement_cost);
                 }
                 Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::O)) => {
-                    if g.movement_cost > 0 {
-                        g.movement_cost -= 1;
+                    if g.movement_cost >= 1.0 {
+                        g.movement_cost -= 1.0;
                     }
                     println!("New movement cost: {}", g.movement_cost);
                 }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::I)) => {
+                    let extended = &cell::brain::EXTENDED_INSTRUCTIONS;
+                    if g.rules.instruction_set.weight(extended[0]) > 0.0 {
+                        g.rules.instruction_set.disable(extended);
+                        println!("Extended instructions disabled");
+                    } else {
+                        g.rules.instruction_set.enable(extended);
+                        println!("Extended instructions enabled");
+                    }
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::C)) => {
+                    use cell::controller::ControllerKind;
+                    g.rules.controller = match g.rules.controller {
+                        ControllerKind::Mep => ControllerKind::Neural,
+                        ControllerKind::Neural => ControllerKind::Mep,
+                    };
+                    println!("New cells use {:?} controllers", g.rules.controller);
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::Y)) => {
+                    print!("{}", g.stats());
+                    println!("energy: cells {:.3}, food {:.3}", g.cell_energy(), g.food_energy());
+                    println!("since last report: {}", g.take_budget());
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::M)) => {
+                    use cell::MemoryInheritance;
+                    g.rules.memory_inheritance = match g.rules.memory_inheritance {
+                        MemoryInheritance::Reset => MemoryInheritance::Copy,
+                        MemoryInheritance::Copy => MemoryInheritance::Average,
+                        MemoryInheritance::Average => MemoryInheritance::Message,
+                        MemoryInheritance::Message => MemoryInheritance::Reset,
+                    };
+                    println!("Memory inheritance: {:?}", g.rules.memory_inheritance);
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::E)) => {
+                    use cell::Senses;
+                    if g.rules.senses == Senses::none() {
+                        g.rules.senses = Senses::all();
+                        println!("Neighbor senses enabled");
+                    } else {
+                        g.rules.senses = Senses::none();
+                        println!("Neighbor senses disabled");
+                    }
+                }
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::Q)) => {
+                    g.rules.sense_radius = g.rules.sense_radius % cell::MAX_SENSE_RADIUS + 1;
+                    println!("Sense radius: {}", g.rules.sense_radius);
+                }
                 Event::KeyboardInput(ElementState::Pressed, _, Some(VKC::R)) => {
                     g.randomize(&mut rng);
                 }
@@ -352,21 +740,13 @@ fn main() {
                     last_mouse_pos = (x, y);
                 }
                 Event::MouseInput(ElementState::Released, MouseButton::Left) => {
-                    let relative_coord = (last_mouse_pos.0 as f32 - center_mouse_coord.0,
-                                          last_mouse_pos.1 as f32 - center_mouse_coord.1);
-
-                    let hex = (center.0 + relative_coord.0 * hex_per_width_pixel,
-                               center.1 - relative_coord.1 * hex_per_height_pixel);
-                    // Adjust the width based on the height.
-                    let hex = (if hex.1 as isize % 2 == 0 {
-                        hex.0 - 0.25
-                    } else {
-                        hex.0 + 0.25
-                    },
-                               hex.1);
-                    if hex.0 > 0.0 && hex.0 < g.width as f32 && hex.1 > 0.0 &&
-                       hex.1 < g.height as f32 {
-                        println!("{:?}", g.hex(hex.0 as usize, hex.1 as usize));
+                    if let Some((x, y)) = mouse_hex(&g,
+                                                    last_mouse_pos,
+                                                    center_mouse_coord,
+                                                    center,
+                                                    (hex_per_width_pixel, hex_per_height_pixel)) {
+                        println!("{:?}", g.hex(x, y));
+                        selected_hex = Some((x, y));
                     }
                 }
                 Event::MouseInput(state, MouseButton::Right) => {
@@ -385,6 +765,32 @@ fn main() {
     }
 }
 
+/// Finds the hex under the mouse, if there is one.
+fn mouse_hex(g: &grid::Grid,
+             mouse_pos: (i32, i32),
+             center_mouse_coord: (f32, f32),
+             center: (f32, f32),
+             hex_per_pixel: (f32, f32))
+             -> Option<(usize, usize)> {
+    let relative_coord = (mouse_pos.0 as f32 - center_mouse_coord.0,
+                          mouse_pos.1 as f32 - center_mouse_coord.1);
+
+    let hex = (center.0 + relative_coord.0 * hex_per_pixel.0,
+               center.1 - relative_coord.1 * hex_per_pixel.1);
+    // Adjust the width based on the height.
+    let hex = (if hex.1 as isize % 2 == 0 {
+        hex.0 - 0.25
+    } else {
+        hex.0 + 0.25
+    },
+               hex.1);
+    if hex.0 > 0.0 && hex.0 < g.width as f32 && hex.1 > 0.0 && hex.1 < g.height as f32 {
+        Some((hex.0 as usize, hex.1 as usize))
+    } else {
+        None
+    }
+}
+
 fn append_circle(v: &mut Vec<QBezier>,
                  radius: f32,
                  circle_scale: f32,
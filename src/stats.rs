@@ -0,0 +1,92 @@
+use itertools::Itertools;
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+
+/// How a value is distributed across the population.
+#[derive(Clone, Debug)]
+pub struct Distribution {
+    pub min: f64,
+    pub median: f64,
+    pub mean: f64,
+    pub max: f64,
+}
+
+impl Distribution {
+    pub fn new<I>(values: I) -> Self
+        where I: Iterator<Item = f64>
+    {
+        let mut v = values.collect_vec();
+        if v.is_empty() {
+            return Distribution {
+                min: 0.0,
+                median: 0.0,
+                mean: 0.0,
+                max: 0.0,
+            };
+        }
+        v.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        Distribution {
+            min: v[0],
+            median: v[v.len() / 2],
+            mean: v.iter().fold(0.0, |acc, &n| acc + n) / v.len() as f64,
+            max: v[v.len() - 1],
+        }
+    }
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "min {:.3}, median {:.3}, mean {:.3}, max {:.3}",
+               self.min,
+               self.median,
+               self.mean,
+               self.max)
+    }
+}
+
+/// A snapshot of the population.
+#[derive(Clone, Debug)]
+pub struct Stats {
+    pub population: usize,
+    pub distributions: Vec<(&'static str, Distribution)>,
+}
+
+impl Stats {
+    fn csv_header(&self) -> String {
+        let mut s = String::from("population");
+        for &(name, _) in &self.distributions {
+            s.push_str(&format!(",{0}_min,{0}_median,{0}_mean,{0}_max", name));
+        }
+        s
+    }
+
+    fn csv_row(&self) -> String {
+        let mut s = format!("{}", self.population);
+        for &(_, ref d) in &self.distributions {
+            s.push_str(&format!(",{},{},{},{}", d.min, d.median, d.mean, d.max));
+        }
+        s
+    }
+
+    /// Appends the stats as a row of a CSV file, writing the header if the file is new.
+    pub fn append_to(&self, path: &str) -> io::Result<()> {
+        let mut f = try!(OpenOptions::new().append(true).create(true).open(path));
+        if try!(f.metadata()).len() == 0 {
+            try!(writeln!(f, "{}", self.csv_header()));
+        }
+        writeln!(f, "{}", self.csv_row())
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "population: {}", self.population));
+        for &(name, ref d) in &self.distributions {
+            try!(writeln!(f, "{}: {}", name, d));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+//! Records everything a tagged cell senses and decides, one row per cycle, until it dies.
+
+use super::cell::{brain, disasm, Decision, DIRECTIONS};
+use super::cell::controller::Mind;
+use super::fluid::{FLUID_NAMES, TOTAL_FLUIDS};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+pub struct Trace {
+    pub id: u64,
+    file: BufWriter<File>,
+    cycle: usize,
+}
+
+/// What the tagged cell sensed and decided in one cycle.
+pub struct Record {
+    pub x: usize,
+    pub y: usize,
+    pub inhale: f64,
+    /// Every input of its controller, laid out by `inputs`.
+    pub inputs: Vec<f64>,
+    /// The raw outputs of its brain.
+    pub actions: Vec<f64>,
+    pub decision: Decision,
+}
+
+impl Trace {
+    /// Creates the trace file and writes the CSV header, labeling the memory inputs for the
+    /// cell's kind of controller.
+    pub fn create(id: u64, mind: &Mind, path: &str) -> io::Result<Trace> {
+        let mut file = BufWriter::new(try!(File::create(path)));
+        let mut header = String::from("cycle,x,y,inhale,choice,receptive");
+        for d in &DIRECTIONS {
+            for f in &FLUID_NAMES {
+                header.push_str(&format!(",diffusion[{:?}][{}]", d, f));
+            }
+        }
+        for i in 0..brain::CONST_INPUTS + brain::STATIC_INPUTS {
+            header.push_str(&format!(",{}", disasm::input_label(i)));
+        }
+        for label in disasm::memory_labels(mind) {
+            header.push_str(&format!(",{}", label));
+        }
+        for o in 0..brain::STATIC_OUTPUTS {
+            header.push_str(&format!(",{}", disasm::output_label(o)));
+        }
+        try!(writeln!(file, "{}", header));
+        Ok(Trace {
+            id: id,
+            file: file,
+            cycle: 0,
+        })
+    }
+
+    /// Writes a row with the cell's state, its inputs, its outputs and the decision made from
+    /// them.
+    pub fn record(&mut self, record: &Record) -> io::Result<()> {
+        let decision = &record.decision;
+        let mut row = format!("{},{},{},{},\"{:?}\",{}",
+                              self.cycle,
+                              record.x,
+                              record.y,
+                              record.inhale,
+                              decision.choice,
+                              decision.receptive);
+        for c in decision.coefficients.iter().flat_map(|c| c[..TOTAL_FLUIDS].iter()) {
+            row.push_str(&format!(",{}", c));
+        }
+        for v in record.inputs.iter().chain(&record.actions) {
+            row.push_str(&format!(",{}", v));
+        }
+        self.cycle += 1;
+        writeln!(self.file, "{}", row)
+    }
+}
+
+/// Lays out every input a controller reads, from the constants through the sensors to the memory
+/// of a brain or the hidden layer of a network.
+pub fn inputs(sensors: &[f64], mind: &Mind) -> Vec<f64> {
+    match *mind {
+        Mind::Mep(ref b) => b.full_inputs(sensors),
+        Mind::Neural(ref n) => {
+            let mut inputs = brain::full_inputs(sensors, &[]);
+            inputs.truncate(brain::CONST_INPUTS + brain::STATIC_INPUTS);
+            inputs.extend_from_slice(&n.hidden);
+            inputs
+        }
+    }
+}